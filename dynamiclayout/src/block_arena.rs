@@ -0,0 +1,142 @@
+use {DynamicLayout, AccessDynamicField, CopyDynamicField, LayoutError, LayoutErrorKind, OffsetType, LengthType,
+     layout_size};
+use layout_rules::round_up;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArenaMode {
+    /// `allocate` fails once all slots are in use until `reset` is called.
+    Linear,
+    /// `allocate` wraps around and reuses the oldest slot, e.g. for per-frame data.
+    Ring,
+}
+
+/// A slot of a `BlockArena`, with the range to bind for it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArenaSlot {
+    pub index: usize,
+    pub offset: OffsetType,
+    pub size: LengthType,
+}
+
+/// Many instances of the same block packed into one buffer, each starting at a multiple of the
+/// offset alignment the API requires for binding ranges (`GL_UNIFORM_BUFFER_OFFSET_ALIGNMENT`).
+pub struct BlockArena<T: DynamicLayout> {
+    layout: T::Layout,
+    mode: ArenaMode,
+    block_size: usize,
+    slot_stride: usize,
+    capacity: usize,
+    next: usize,
+    bytes: Vec<u8>,
+}
+
+impl<T: DynamicLayout> BlockArena<T> {
+    /// `block_size` is the size of the block's data, as reported by the API (e.g.
+    /// `GL_UNIFORM_BLOCK_DATA_SIZE`) or given by `LayoutRules::size`. It can be more than
+    /// `layout_size`, which is only used as a lower bound, when the block ends in padding.
+    ///
+    /// Fails if the arena would not fit in the range of `OffsetType`.
+    pub fn new(layout: T::Layout,
+               block_size: usize,
+               offset_alignment: usize,
+               capacity: usize,
+               mode: ArenaMode)
+               -> Result<BlockArena<T>, LayoutError> {
+        let block_size = ::std::cmp::max(layout_size::<T>(&layout), block_size);
+        let slot_stride = round_up(block_size, offset_alignment);
+        let total = slot_stride.checked_mul(capacity)
+            .ok_or(LayoutError::new(LayoutErrorKind::OffsetOverflow))?;
+        if total > OffsetType::max_value() as usize {
+            return Err(LayoutErrorKind::OffsetOverflow.into());
+        }
+        Ok(BlockArena {
+            layout: layout,
+            mode: mode,
+            block_size: block_size,
+            slot_stride: slot_stride,
+            capacity: capacity,
+            next: 0,
+            bytes: vec![0; total],
+        })
+    }
+
+    pub fn layout(&self) -> &T::Layout {
+        &self.layout
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of slots handed out since the last `reset`, at most `capacity`.
+    pub fn len(&self) -> usize {
+        ::std::cmp::min(self.next, self.capacity)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next == 0
+    }
+
+    /// Distance between the starts of two slots.
+    pub fn slot_stride(&self) -> usize {
+        self.slot_stride
+    }
+
+    pub fn slot(&self, index: usize) -> Option<ArenaSlot> {
+        if index >= self.capacity {
+            return None;
+        }
+        Some(ArenaSlot {
+            index: index,
+            offset: (index * self.slot_stride) as OffsetType,
+            size: self.block_size as LengthType,
+        })
+    }
+
+    pub fn allocate(&mut self) -> Option<ArenaSlot> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let index = match self.mode {
+            ArenaMode::Linear if self.next >= self.capacity => return None,
+            ArenaMode::Linear => self.next,
+            ArenaMode::Ring => self.next % self.capacity,
+        };
+        self.next += 1;
+        self.slot(index)
+    }
+
+    /// Makes all slots available again. The contents are kept.
+    pub fn reset(&mut self) {
+        self.next = 0;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn accessor<'a>(&'a mut self, slot: ArenaSlot) -> <T as AccessDynamicField<'a>>::Accessor
+        where T: AccessDynamicField<'a>
+    {
+        if slot.index >= self.capacity {
+            panic!("BlockArena slot out of bounds: the capacity is {} but the index is {}",
+                   self.capacity,
+                   slot.index);
+        }
+        let start = slot.index * self.slot_stride;
+        let slot_bytes = &mut self.bytes[start..start + self.slot_stride];
+        unsafe { T::accessor_from_layout(&self.layout, slot_bytes.as_mut_ptr(), slot_bytes.len()) }
+    }
+}
+
+impl<T: DynamicLayout + CopyDynamicField> BlockArena<T> {
+    pub fn write(&mut self, slot: ArenaSlot, value: &T) {
+        if slot.index >= self.capacity {
+            panic!("BlockArena slot out of bounds: the capacity is {} but the index is {}",
+                   self.capacity,
+                   slot.index);
+        }
+        let start = slot.index * self.slot_stride;
+        unsafe { T::write_field(&self.layout, self.bytes[start..].as_mut_ptr(), value) }
+    }
+}
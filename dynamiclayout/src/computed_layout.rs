@@ -0,0 +1,128 @@
+use {LayoutInfo, LoadStructLayout, LayoutError, LayoutErrorKind, OffsetType, StrideType};
+use type_info::{DescribeType, TypeInfo, StructInfo};
+use layout_rules::LayoutRules;
+
+/// Layout of a field computed from layout rules, owning what its `LayoutInfo` refers to.
+pub enum ComputedInfo {
+    Info(LayoutInfo<'static>),
+    Struct(ComputedLayout),
+    /// Offset and stride of the array, and the layout of an element relative to its start.
+    StructArray(OffsetType, StrideType, ComputedLayout),
+    /// Offset and stride of the outer array, and the layout of an inner array.
+    ArrayOfArrays(OffsetType, StrideType, LayoutInfo<'static>),
+}
+
+impl ComputedInfo {
+    pub fn info(&self) -> LayoutInfo {
+        match *self {
+            ComputedInfo::Info(info) => info,
+            ComputedInfo::Struct(ref fields) => LayoutInfo::StructField(fields),
+            ComputedInfo::StructArray(offset, stride, ref element) => {
+                LayoutInfo::StructArrayStrideField(offset, stride, element)
+            }
+            ComputedInfo::ArrayOfArrays(offset, stride, ref inner) => {
+                LayoutInfo::ArrayOfArraysField(offset, stride, inner)
+            }
+        }
+    }
+}
+
+/// Layout of a struct computed from layout rules, for `DynamicLayout::load_layout`.
+pub struct ComputedLayout {
+    fields: Vec<(String, ComputedInfo)>,
+}
+
+impl ComputedLayout {
+    pub fn new(fields: Vec<(String, ComputedInfo)>) -> ComputedLayout {
+        ComputedLayout { fields: fields }
+    }
+}
+
+impl LoadStructLayout for ComputedLayout {
+    fn get_field_layout(&self, field_name: &str) -> Option<LayoutInfo> {
+        self.fields.iter().find(|f| f.0 == field_name).map(|f| f.1.info())
+    }
+
+    fn field_names(&self) -> Vec<&str> {
+        self.fields.iter().map(|f| &f.0[..]).collect()
+    }
+}
+
+/// Layout `rules` give the fields of the struct `T`.
+///
+/// Fails if `T` is not a struct, and for arrays of arrays of anything but basic types, whose
+/// layout infos would have to refer to data of their own.
+pub fn compute_layout<T, R>(rules: &R) -> Result<ComputedLayout, LayoutError>
+    where T: DescribeType,
+          R: LayoutRules
+{
+    match T::TYPE_INFO {
+        TypeInfo::Struct(info) => compute_struct(rules, info, 0),
+        _ => Err(LayoutErrorKind::UnsupportedType.into()),
+    }
+}
+
+/// Layout of a struct placed at `offset`.
+pub fn compute_struct<R: LayoutRules>(rules: &R,
+                                      info: &StructInfo,
+                                      offset: usize)
+                                      -> Result<ComputedLayout, LayoutError> {
+    let (offsets, _) = rules.struct_offsets(info);
+    let mut fields = Vec::with_capacity(info.fields.len());
+    for (field, field_offset) in info.fields.iter().zip(offsets) {
+        let computed = compute_field(rules, field.ty, offset + field_offset)
+            .map_err(|e| e.in_field(field.name))?;
+        fields.push((field.name.to_string(), computed));
+    }
+    Ok(ComputedLayout { fields: fields })
+}
+
+/// Layout of a value of type `ty` placed at `offset`.
+pub fn compute_field<R: LayoutRules>(rules: &R,
+                                     ty: &TypeInfo,
+                                     offset: usize)
+                                     -> Result<ComputedInfo, LayoutError> {
+    if offset > OffsetType::max_value() as usize {
+        return Err(LayoutErrorKind::OffsetOverflow.into());
+    }
+    let offset = offset as OffsetType;
+    Ok(match *ty {
+        TypeInfo::Scalar(_) |
+        TypeInfo::Vector(..) |
+        TypeInfo::PackedVector(..) => ComputedInfo::Info(LayoutInfo::PrimitiveField(offset)),
+        TypeInfo::Matrix { .. } => {
+            let matrix_stride = rules.matrix_stride(ty) as StrideType;
+            ComputedInfo::Info(LayoutInfo::ArrayField(offset, matrix_stride))
+        }
+        TypeInfo::Array(element, _) |
+        TypeInfo::RuntimeArray(element) => {
+            let stride = rules.array_stride(element) as StrideType;
+            match *element {
+                TypeInfo::Scalar(_) |
+                TypeInfo::Vector(..) |
+                TypeInfo::PackedVector(..) => {
+                    ComputedInfo::Info(LayoutInfo::ArrayField(offset, stride))
+                }
+                TypeInfo::Matrix { .. } => {
+                    let matrix_stride = rules.matrix_stride(element) as StrideType;
+                    ComputedInfo::Info(LayoutInfo::MatrixArrayField(offset, stride, matrix_stride))
+                }
+                TypeInfo::Struct(info) => {
+                    ComputedInfo::StructArray(offset, stride, compute_struct(rules, info, 0)?)
+                }
+                TypeInfo::Array(..) |
+                TypeInfo::RuntimeArray(_) => {
+                    match compute_field(rules, element, 0)? {
+                        ComputedInfo::Info(inner) => {
+                            ComputedInfo::ArrayOfArrays(offset, stride, inner)
+                        }
+                        _ => return Err(LayoutErrorKind::UnsupportedType.into()),
+                    }
+                }
+            }
+        }
+        TypeInfo::Struct(info) => {
+            ComputedInfo::Struct(compute_struct(rules, info, offset as usize)?)
+        }
+    })
+}
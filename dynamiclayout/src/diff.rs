@@ -0,0 +1,53 @@
+use {LayoutDynamicField, FieldSpan};
+use type_info::ScalarType;
+
+/// A field whose value differs between two buffers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub path: String,
+    pub type_name: &'static str,
+    pub span: FieldSpan,
+    pub old: String,
+    pub new: String,
+}
+
+/// Compares the fields of two buffers with the same layout and returns the ones that changed, in
+/// layout order. Padding is ignored. Both buffers must contain all fields of the layout.
+pub fn diff<T: LayoutDynamicField>(layout: &T::Layout, old: &[u8], new: &[u8]) -> Vec<FieldChange> {
+    T::get_named_field_spans(layout, "")
+        .filter_map(|named| {
+            let start = named.span.offset as usize;
+            let end = start + named.span.length as usize;
+            let (old, new) = (&old[start..end], &new[start..end]);
+            if old == new {
+                return None;
+            }
+            Some(FieldChange {
+                old: format_value(named.scalar_type, old),
+                new: format_value(named.scalar_type, new),
+                path: named.path,
+                type_name: named.type_name,
+                span: named.span,
+            })
+        })
+        .collect()
+}
+
+// Formats a scalar as is and anything with more components as a list
+fn format_value(scalar_type: ScalarType, bytes: &[u8]) -> String {
+    let components: Vec<String> = bytes.chunks(4)
+        .map(|chunk| {
+            let mut raw = [0u8; 4];
+            raw[..chunk.len()].copy_from_slice(chunk);
+            match scalar_type {
+                ScalarType::Float => format!("{:?}", f32::from_ne_bytes(raw)),
+                ScalarType::Int => format!("{}", i32::from_ne_bytes(raw)),
+                ScalarType::Uint => format!("{}", u32::from_ne_bytes(raw)),
+            }
+        })
+        .collect();
+    if components.len() == 1 {
+        return components.into_iter().next().unwrap();
+    }
+    format!("[{}]", components.join(", "))
+}
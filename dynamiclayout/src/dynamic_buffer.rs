@@ -0,0 +1,135 @@
+use std::cmp;
+use std::mem;
+use {DynamicLayout, AccessDynamicField, CopyDynamicField, LoadStructLayout, LayoutError, FieldSpan, OffsetType,
+     LengthType, layout_size};
+use upload_ranges::{coalesce_spans, changed_spans, CoalesceOptions};
+
+/// Owns a zero-initialized byte buffer and the layout of `T` in it.
+pub struct DynamicBuffer<T: DynamicLayout> {
+    layout: T::Layout,
+    /// Size the buffer was created or last resized with, kept when the layout changes.
+    data_size: usize,
+    bytes: Vec<u8>,
+    /// Contents at the last `take_dirty_ranges`, if dirty tracking is enabled. Empty when
+    /// everything is dirty.
+    shadow: Option<Vec<u8>>,
+}
+
+impl<T: DynamicLayout> DynamicBuffer<T> {
+    /// Buffer that ends with the last field of the layout. Blocks that are bound as a whole need
+    /// `with_size` if their data size is larger, e.g. when they end in a `vec3`.
+    pub fn new(layout: T::Layout) -> DynamicBuffer<T> {
+        DynamicBuffer::with_size(layout, 0)
+    }
+
+    /// Buffer of the block data size `size`, as reported by the API (e.g.
+    /// `GL_UNIFORM_BLOCK_DATA_SIZE`) or given by `LayoutRules::size`, or larger to make room for
+    /// the elements of a runtime-sized array. It is never smaller than `layout_size`.
+    pub fn with_size(layout: T::Layout, size: usize) -> DynamicBuffer<T> {
+        let data_size = size;
+        let size = cmp::max(layout_size::<T>(&layout), size);
+        DynamicBuffer {
+            layout: layout,
+            data_size: data_size,
+            bytes: vec![0; size],
+            shadow: None,
+        }
+    }
+
+    pub fn load(layout_info: &LoadStructLayout) -> Result<DynamicBuffer<T>, LayoutError> {
+        T::load_layout(layout_info).map(DynamicBuffer::new)
+    }
+
+    pub fn layout(&self) -> &T::Layout {
+        &self.layout
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Resizes the buffer, but never below the size the layout needs. New bytes are zero.
+    pub fn resize(&mut self, size: usize) {
+        self.data_size = size;
+        let size = cmp::max(layout_size::<T>(&self.layout), size);
+        self.bytes.resize(size, 0);
+        self.mark_all_dirty();
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn accessor<'a>(&'a mut self) -> <T as AccessDynamicField<'a>>::Accessor
+        where T: AccessDynamicField<'a>
+    {
+        unsafe { T::accessor_from_layout(&self.layout, self.bytes.as_mut_ptr(), self.bytes.len()) }
+    }
+
+    /// With dirty tracking `take_dirty_ranges` only returns the fields that changed since its
+    /// last call. Everything is dirty right after enabling it.
+    pub fn set_dirty_tracking(&mut self, enabled: bool) {
+        self.shadow = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        if let Some(ref mut shadow) = self.shadow {
+            shadow.clear();
+        }
+    }
+
+    /// Merged byte ranges of the fields written since the last call, or of all fields if dirty
+    /// tracking is disabled. Bytes past the fixed part of the layout, i.e. the elements of a
+    /// runtime-sized array, are treated as a single field.
+    pub fn take_dirty_ranges(&mut self, options: &CoalesceOptions) -> Vec<FieldSpan> {
+        let size = layout_size::<T>(&self.layout);
+        let tail = if self.bytes.len() > size {
+            Some(FieldSpan {
+                offset: size as OffsetType,
+                length: (self.bytes.len() - size) as LengthType,
+            })
+        } else {
+            None
+        };
+        let spans = T::get_field_spans(&self.layout).chain(tail);
+        match self.shadow {
+            Some(ref mut shadow) => {
+                let dirty = changed_spans(spans, shadow, &self.bytes);
+                shadow.clear();
+                shadow.extend_from_slice(&self.bytes);
+                coalesce_spans(dirty, options)
+            }
+            None => coalesce_spans(spans, options),
+        }
+    }
+}
+
+impl<T: DynamicLayout + CopyDynamicField> DynamicBuffer<T> {
+    pub fn write(&mut self, value: &T) {
+        unsafe { T::write_field(&self.layout, self.bytes.as_mut_ptr(), value) }
+    }
+
+    /// Fields the layout does not cover, e.g. truncated array elements, are zero.
+    pub fn read(&self) -> T {
+        unsafe {
+            let mut value: T = mem::zeroed();
+            T::read_field(&self.layout, self.bytes.as_ptr(), &mut value);
+            value
+        }
+    }
+
+    /// Switches to a new layout, keeping the values of all fields. The buffer is resized to what
+    /// the new layout needs but not below the size it was created or last resized with. Elements
+    /// of runtime-sized arrays are not part of the value and are zero afterwards.
+    pub fn set_layout(&mut self, layout: T::Layout) {
+        let value = self.read();
+        self.bytes = vec![0; cmp::max(layout_size::<T>(&layout), self.data_size)];
+        self.layout = layout;
+        self.write(&value);
+        self.mark_all_dirty();
+    }
+}
@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::fmt;
+use type_info::GlslType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutErrorKind {
+    /// The layout has no entry for the field.
+    MissingField,
+    /// The layout info is of the wrong kind for the field, e.g. an array for a scalar.
+    UnexpectedLayout {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The shader declares the field with a different type.
+    TypeMismatch {
+        expected: GlslType,
+        found: GlslType,
+    },
+    ArrayLengthMismatch { declared: usize, len: usize },
+    /// The field does not fit in the range of `OffsetType`.
+    OffsetOverflow,
+    /// The program has no active block with the name.
+    MissingBlock(String),
+    /// Introspection data that cannot be turned into a layout.
+    InvalidMember(String),
+    /// The layout has a field the Rust struct does not, which strict loading rejects.
+    UnmatchedMember,
+    /// The type cannot be laid out or declared as a block, e.g. because it is not a struct.
+    UnsupportedType,
+    /// The packing, e.g. std430, is not allowed for uniform blocks.
+    UnsupportedPacking(&'static str),
+}
+
+/// Why a layout could not be loaded, and for which field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutError {
+    /// Path of the field, e.g. `compound.four` or `lights[1]`. Empty for the block itself.
+    pub path: String,
+    pub kind: LayoutErrorKind,
+}
+
+impl LayoutError {
+    pub fn new(kind: LayoutErrorKind) -> LayoutError {
+        LayoutError {
+            path: String::new(),
+            kind: kind,
+        }
+    }
+
+    /// Marks the error as coming from within the field `name`.
+    pub fn in_field(mut self, name: &str) -> LayoutError {
+        self.path = if self.path.is_empty() {
+            name.to_string()
+        } else if self.path.starts_with('[') {
+            format!("{}{}", name, self.path)
+        } else {
+            format!("{}.{}", name, self.path)
+        };
+        self
+    }
+
+    /// Marks the error as coming from within array element `index`.
+    pub fn in_element(mut self, index: usize) -> LayoutError {
+        self.path = if self.path.is_empty() || self.path.starts_with('[') {
+            format!("[{}]{}", index, self.path)
+        } else {
+            format!("[{}].{}", index, self.path)
+        };
+        self
+    }
+}
+
+impl From<LayoutErrorKind> for LayoutError {
+    fn from(kind: LayoutErrorKind) -> LayoutError {
+        LayoutError::new(kind)
+    }
+}
+
+impl fmt::Display for LayoutErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayoutErrorKind::MissingField => write!(f, "missing from the layout"),
+            LayoutErrorKind::UnexpectedLayout { expected, found } => {
+                write!(f, "expected {} layout, found {}", expected, found)
+            }
+            LayoutErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "expected {} but the shader declares {}", expected, found)
+            }
+            LayoutErrorKind::ArrayLengthMismatch { declared, len } => {
+                write!(f, "the shader declares {} elements but the Rust array has {}", declared, len)
+            }
+            LayoutErrorKind::OffsetOverflow => write!(f, "offset overflows the offset type"),
+            LayoutErrorKind::MissingBlock(ref block) => write!(f, "no active block {}", block),
+            LayoutErrorKind::InvalidMember(ref reason) => write!(f, "invalid member: {}", reason),
+            LayoutErrorKind::UnmatchedMember => write!(f, "not a field of the Rust struct"),
+            LayoutErrorKind::UnsupportedType => write!(f, "the type is not supported"),
+            LayoutErrorKind::UnsupportedPacking(packing) => {
+                write!(f, "{} is not allowed for uniform blocks", packing)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}: {}", self.path, self.kind)
+        }
+    }
+}
+
+impl Error for LayoutError {}
+
+/// Error from parsing shader source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 1-based line of the source the error was found on.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
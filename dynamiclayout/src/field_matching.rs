@@ -0,0 +1,90 @@
+use {LayoutDynamicField, LayoutInfo, LoadStructLayout, LoadOptions, FieldMatching, LayoutError,
+     LayoutErrorKind, join_field_path, index_field_path};
+use type_info::{DescribeType, TypeInfo, StructInfo};
+
+/// Fields that only one side has when matching a Rust struct against a layout.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldReport {
+    /// Paths of Rust fields the layout has no entry for.
+    pub rust_only: Vec<String>,
+    /// Paths of layout entries without a Rust field, e.g. a uniform the struct forgot.
+    pub shader_only: Vec<String>,
+}
+
+impl FieldReport {
+    pub fn is_exact(&self) -> bool {
+        self.rust_only.is_empty() && self.shader_only.is_empty()
+    }
+}
+
+/// Compares the fields of `T` with the ones in `layout`, including those of nested structs.
+///
+/// Arrays of structs are only compared element by element if the layout lists the elements.
+/// Layouts that cannot enumerate their fields report no shader-only fields.
+pub fn match_fields<T: DescribeType>(layout: &LoadStructLayout) -> FieldReport {
+    let mut report = FieldReport::default();
+    if let TypeInfo::Struct(info) = T::TYPE_INFO {
+        compare_struct(info, layout, "", &mut report);
+    }
+    report
+}
+
+fn compare_struct(info: &StructInfo,
+                  layout: &LoadStructLayout,
+                  path: &str,
+                  report: &mut FieldReport) {
+    for field in info.fields {
+        let field_path = join_field_path(path, field.name);
+        match layout.get_field_layout(field.name) {
+            Some(field_layout) => compare_field(field.ty, field_layout, &field_path, report),
+            None => report.rust_only.push(field_path),
+        }
+    }
+    for name in layout.field_names() {
+        if !info.fields.iter().any(|field| field.name == name) {
+            report.shader_only.push(join_field_path(path, name));
+        }
+    }
+}
+
+fn compare_field(ty: &TypeInfo, layout: LayoutInfo, path: &str, report: &mut FieldReport) {
+    match (*ty, layout) {
+        (_, LayoutInfo::ArrayLength(_, inner)) => compare_field(ty, inner.field_layout(), path, report),
+        (TypeInfo::Struct(info), LayoutInfo::StructField(inner)) => {
+            compare_struct(info, inner, path, report)
+        }
+        (TypeInfo::Array(&TypeInfo::Struct(info), _), LayoutInfo::StructArrayField(elements)) => {
+            for (i, element) in elements.iter().enumerate() {
+                compare_struct(info, *element, &index_field_path(path, i), report);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Loads the layout of the struct `T` with the field matching `options` ask for. This is what
+/// the derived `DynamicLayout::load_layout_with_options` calls.
+pub fn load_struct_layout<T>(layout: &LoadStructLayout,
+                             options: &LoadOptions)
+                             -> Result<T::Layout, LayoutError>
+    where T: LayoutDynamicField + DescribeType
+{
+    if options.fields == FieldMatching::Strict {
+        if let Some(path) = match_fields::<T>(layout).shader_only.first() {
+            return Err(LayoutError::new(LayoutErrorKind::UnmatchedMember).in_field(path));
+        }
+    }
+    T::make_layout(LayoutInfo::StructField(layout), options)
+}
+
+/// The layout of a field for the derived accessors.
+///
+/// # Panics
+///
+/// If a lenient load found no layout for the field.
+pub fn present_layout<'a, L>(layout: &'a Option<L>, field_name: &str) -> &'a L {
+    match *layout {
+        Some(ref layout) => layout,
+        None => panic!("No accessor for `{}`, the layout does not have the field", field_name),
+    }
+}
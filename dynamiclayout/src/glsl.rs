@@ -0,0 +1,545 @@
+use {LayoutError, LayoutErrorKind};
+use type_info::{DescribeType, GlslType, StructInfo, TypeInfo};
+pub use error::ParseError;
+
+/// Layout qualifier of a generated block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Packing {
+    Std140,
+    /// Only allowed for buffer blocks.
+    Std430,
+    Shared,
+    Packed,
+}
+
+impl Packing {
+    pub fn qualifier(&self) -> &'static str {
+        match *self {
+            Packing::Std140 => "std140",
+            Packing::Std430 => "std430",
+            Packing::Shared => "shared",
+            Packing::Packed => "packed",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockKind {
+    Uniform,
+    Buffer,
+}
+
+/// GLSL declaration of a block with the fields of `T`, preceded by declarations of the structs
+/// it uses. The block is named after the Rust struct.
+///
+/// ```text
+/// struct Light {
+///     vec3 position;
+///     float radius;
+/// };
+///
+/// layout(std140) uniform Scene {
+///     Light lights[4];
+/// } scene;
+/// ```
+///
+/// Fails if `T` is not a struct, for std430 packing of a uniform block, and for runtime-sized
+/// arrays anywhere but as the last member of a buffer block.
+pub fn block_declaration<T: DescribeType>(kind: BlockKind,
+                                          packing: Packing,
+                                          instance_name: Option<&str>)
+                                          -> Result<String, LayoutError> {
+    let info = match T::TYPE_INFO {
+        TypeInfo::Struct(info) => info,
+        _ => return Err(LayoutErrorKind::UnsupportedType.into()),
+    };
+    if kind == BlockKind::Uniform && packing == Packing::Std430 {
+        return Err(LayoutErrorKind::UnsupportedPacking(packing.qualifier()).into());
+    }
+    for (i, field) in info.fields.iter().enumerate() {
+        let unsupported = match *field.ty {
+            TypeInfo::RuntimeArray(element) if kind == BlockKind::Buffer && i + 1 == info.fields.len() => {
+                has_runtime_array(element)
+            }
+            ref other => has_runtime_array(other),
+        };
+        if unsupported {
+            return Err(LayoutError::new(LayoutErrorKind::UnsupportedType).in_field(field.name));
+        }
+    }
+    let mut source = String::new();
+    for nested in used_structs(info) {
+        source.push_str(&struct_declaration(nested));
+        source.push('\n');
+    }
+    let keyword = match kind {
+        BlockKind::Uniform => "uniform",
+        BlockKind::Buffer => "buffer",
+    };
+    source.push_str(&format!("layout({}) {} {} {{\n", packing.qualifier(), keyword, info.name));
+    source.push_str(&member_declarations(info));
+    match instance_name {
+        Some(name) => source.push_str(&format!("}} {};\n", name)),
+        None => source.push_str("};\n"),
+    }
+    Ok(source)
+}
+
+/// GLSL declaration of the struct, without the structs its fields use.
+pub fn struct_declaration(info: &StructInfo) -> String {
+    format!("struct {} {{\n{}}};\n", info.name, member_declarations(info))
+}
+
+/// Structs used by the fields of `info`, also indirectly, each one before the structs using it.
+pub fn used_structs(info: &'static StructInfo) -> Vec<&'static StructInfo> {
+    let mut structs = Vec::new();
+    for field in info.fields {
+        collect_structs(field.ty, &mut structs);
+    }
+    structs
+}
+
+fn collect_structs(ty: &'static TypeInfo, structs: &mut Vec<&'static StructInfo>) {
+    match *ty {
+        TypeInfo::Array(element, _) |
+        TypeInfo::RuntimeArray(element) => collect_structs(element, structs),
+        TypeInfo::Struct(info) => {
+            if structs.iter().any(|s| s.name == info.name) {
+                return;
+            }
+            for field in info.fields {
+                collect_structs(field.ty, structs);
+            }
+            structs.push(info);
+        }
+        _ => {}
+    }
+}
+
+fn has_runtime_array(ty: &TypeInfo) -> bool {
+    match *ty {
+        TypeInfo::RuntimeArray(_) => true,
+        TypeInfo::Array(element, _) => has_runtime_array(element),
+        TypeInfo::Struct(info) => info.fields.iter().any(|field| has_runtime_array(field.ty)),
+        _ => false,
+    }
+}
+
+fn member_declarations(info: &StructInfo) -> String {
+    info.fields
+        .iter()
+        .map(|field| format!("    {};\n", declaration(field.ty, field.name)))
+        .collect()
+}
+
+// E.g. `float grid[2][3]` for an array of two arrays of three floats
+fn declaration(ty: &TypeInfo, name: &str) -> String {
+    let mut suffix = String::new();
+    let mut ty = ty;
+    loop {
+        match *ty {
+            TypeInfo::Array(element, len) => {
+                suffix.push_str(&format!("[{}]", len));
+                ty = element;
+            }
+            TypeInfo::RuntimeArray(element) => {
+                suffix.push_str("[]");
+                ty = element;
+            }
+            _ => break,
+        }
+    }
+    let type_name = match *ty {
+        TypeInfo::Struct(info) => info.name.to_string(),
+        ref other => GlslType::from_type_info(other).unwrap().to_string(),
+    };
+    format!("{} {}{}", type_name, name, suffix)
+}
+
+/// A member of a parsed struct or block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub type_name: String,
+    pub name: String,
+    /// Array dimensions, outermost first. `None` for a runtime-sized array.
+    pub dimensions: Vec<Option<usize>>,
+}
+
+/// A struct or block declared in GLSL source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    /// `None` for a struct.
+    pub block: Option<BlockKind>,
+    pub members: Vec<Member>,
+    /// 1-based line of the name.
+    pub line: usize,
+}
+
+/// Parses the struct and block declarations in `source`, skipping everything else.
+///
+/// Array sizes can be numbers or names defined by `#define NAME <number>`. Matrices that are
+/// row-major, from their own `row_major` layout qualifier, their block's or the default set by
+/// e.g. `layout(row_major) uniform;`, are rejected since the matrix types of this crate are
+/// column-major.
+pub fn parse_declarations(source: &str) -> Result<Vec<Declaration>, ParseError> {
+    let mut parser = Parser::new(source);
+    let mut declarations = Vec::new();
+    // Matrix order of uniform and buffer blocks without a qualifier of their own
+    let mut default_row_major = [false, false];
+    // Matrix order given by the layout qualifiers before the current token
+    let mut row_major = None;
+    while let Some(token) = parser.next() {
+        match token.text {
+            "layout" => row_major = parser.layout_qualifiers()?,
+            ";" => row_major = None,
+            "struct" => {
+                row_major = None;
+                let line = parser.line();
+                let name = parser.identifier()?;
+                let (members, unqualified_matrices) = parser.members(None)?;
+                if unqualified_matrices {
+                    parser.unqualified_matrix_structs.push(name);
+                }
+                parser.skip_past(";")?;
+                declarations.push(Declaration {
+                    name: name.to_string(),
+                    block: None,
+                    members: members,
+                    line: line,
+                });
+            }
+            "uniform" | "buffer" => {
+                let default = &mut default_row_major[if token.text == "uniform" { 0 } else { 1 }];
+                let qualified = row_major.take();
+                // E.g. `layout(row_major) uniform;` changes the default of the following blocks
+                if parser.peek() == Some(";") {
+                    *default = qualified.unwrap_or(*default);
+                    continue;
+                }
+                let line = parser.line();
+                let name = parser.identifier()?;
+                // Uniforms outside of blocks are not of interest
+                if parser.peek() != Some("{") {
+                    parser.skip_past(";")?;
+                    continue;
+                }
+                let (members, _) = parser.members(Some(qualified.unwrap_or(*default)))?;
+                parser.skip_past(";")?;
+                declarations.push(Declaration {
+                    name: name.to_string(),
+                    block: Some(if token.text == "uniform" {
+                        BlockKind::Uniform
+                    } else {
+                        BlockKind::Buffer
+                    }),
+                    members: members,
+                    line: line,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(declarations)
+}
+
+/// Rust definitions with `#[derive(DynamicLayout)]` of the structs and blocks declared in
+/// `source`, for generating them from a build script. Blocks become structs of the same name.
+pub fn rust_definitions(source: &str) -> Result<String, ParseError> {
+    let declarations = parse_declarations(source)?;
+    let mut output = String::new();
+    for (i, declaration) in declarations.iter().enumerate() {
+        if declarations[..i].iter().any(|d| d.name == declaration.name) {
+            continue;
+        }
+        if i > 0 {
+            output.push('\n');
+        }
+        output.push_str("#[repr(C, packed)]\n");
+        output.push_str("#[derive(Debug, Copy, Clone, DynamicLayout)]\n");
+        output.push_str("#[allow(non_snake_case)]\n");
+        output.push_str(&format!("pub struct {} {{\n", declaration.name));
+        for (j, member) in declaration.members.iter().enumerate() {
+            let is_last = j + 1 == declaration.members.len();
+            let ty = rust_type(member, &declarations[..i], is_last).map_err(|message| {
+                    ParseError {
+                        line: declaration.line,
+                        message: format!("{}.{}: {}", declaration.name, member.name, message),
+                    }
+                })?;
+            output.push_str(&format!("    pub {}: {},\n", member.name, ty));
+        }
+        output.push_str("}\n");
+    }
+    Ok(output)
+}
+
+fn rust_type(member: &Member, declared: &[Declaration], is_last: bool) -> Result<String, String> {
+    if RUST_KEYWORDS.contains(&&member.name[..]) {
+        return Err("the name is a Rust keyword".to_string());
+    }
+    let mut ty = match rust_basic_type(&member.type_name) {
+        Some(ty) => ty,
+        None if declared.iter().any(|d| d.block.is_none() && d.name == member.type_name) => {
+            member.type_name.clone()
+        }
+        None => return Err(format!("no Rust type for {}", member.type_name)),
+    };
+    for (i, dimension) in member.dimensions.iter().enumerate().rev() {
+        ty = match *dimension {
+            Some(len) => format!("[{}; {}]", ty, len),
+            None if i == 0 && is_last => format!("dynamiclayout::unsized_array::Unsized<{}>", ty),
+            None => return Err("only the last member can be runtime-sized".to_string()),
+        };
+    }
+    Ok(ty)
+}
+
+fn rust_basic_type(glsl: &str) -> Option<String> {
+    let ty = match glsl {
+        "float" => "f32".to_string(),
+        "int" => "i32".to_string(),
+        "uint" => "u32".to_string(),
+        "vec2" | "vec3" | "vec4" => format!("dynamiclayout::Vec{}", &glsl[3..]),
+        "ivec2" | "ivec3" | "ivec4" => format!("dynamiclayout::IVec{}", &glsl[4..]),
+        "uvec2" | "uvec3" | "uvec4" => format!("dynamiclayout::UVec{}", &glsl[4..]),
+        "mat2" | "mat3" | "mat4" | "mat2x2" | "mat3x3" | "mat4x4" => {
+            format!("dynamiclayout::Matrix{}", &glsl[3..4])
+        }
+        "mat2x3" | "mat2x4" | "mat3x2" | "mat3x4" | "mat4x2" | "mat4x3" => {
+            format!("dynamiclayout::Matrix{}", &glsl[3..])
+        }
+        _ => return None,
+    };
+    Some(ty)
+}
+
+const RUST_KEYWORDS: &'static [&'static str] =
+    &["as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+      "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+      "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+      "where", "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+      "typeof", "unsized", "virtual", "yield"];
+
+// Qualifiers that may precede the type of a member
+const MEMBER_QUALIFIERS: &'static [&'static str] =
+    &["highp", "mediump", "lowp", "readonly", "writeonly", "coherent", "volatile", "restrict",
+      "precise", "flat", "smooth", "noperspective"];
+
+#[derive(Copy, Clone)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    defines: Vec<(&'a str, usize)>,
+    /// Structs with matrices whose order is that of the block using them.
+    unqualified_matrix_structs: Vec<&'a str>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Parser<'a> {
+        let mut parser = Parser {
+            tokens: Vec::new(),
+            defines: Vec::new(),
+            unqualified_matrix_structs: Vec::new(),
+            position: 0,
+        };
+        let mut in_comment = false;
+        for (i, line) in source.lines().enumerate() {
+            let mut rest = line;
+            if !in_comment && rest.trim_start().starts_with('#') {
+                let words: Vec<_> = rest.trim_start()[1..].split_whitespace().collect();
+                if words.len() == 3 && words[0] == "define" {
+                    if let Ok(value) = words[2].parse() {
+                        parser.defines.push((words[1], value));
+                    }
+                }
+                continue;
+            }
+            while !rest.is_empty() {
+                if in_comment {
+                    match rest.find("*/") {
+                        Some(end) => {
+                            rest = &rest[end + 2..];
+                            in_comment = false;
+                        }
+                        None => break,
+                    }
+                    continue;
+                }
+                if rest.starts_with("//") {
+                    break;
+                }
+                if rest.starts_with("/*") {
+                    in_comment = true;
+                    rest = &rest[2..];
+                    continue;
+                }
+                let c = rest.chars().next().unwrap();
+                let len = if c.is_alphanumeric() || c == '_' {
+                    rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len())
+                } else {
+                    c.len_utf8()
+                };
+                if !c.is_whitespace() {
+                    parser.tokens.push(Token {
+                        text: &rest[..len],
+                        line: i + 1,
+                    });
+                }
+                rest = &rest[len..];
+            }
+        }
+        parser
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|token| token.text)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map(|token| token.line)
+            .unwrap_or(0)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line(),
+            message: message.to_string(),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), ParseError> {
+        if self.peek() != Some(text) {
+            return Err(self.error(&format!("expected `{}`", text)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<&'a str, ParseError> {
+        match self.peek() {
+            Some(text) if text.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                self.position += 1;
+                Ok(text)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    fn skip_past(&mut self, text: &str) -> Result<(), ParseError> {
+        while let Some(token) = self.next() {
+            if token.text == text {
+                return Ok(());
+            }
+        }
+        Err(self.error(&format!("expected `{}`", text)))
+    }
+
+    // `{ ... }` of a struct or block, and whether it has matrices without an order of their own.
+    // `block_row_major` is the matrix order of a block, `None` for a struct, whose matrices are
+    // ordered like the block it is used in.
+    fn members(&mut self, block_row_major: Option<bool>) -> Result<(Vec<Member>, bool), ParseError> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        let mut row_major = None;
+        let mut unqualified_matrices = false;
+        while self.peek() != Some("}") {
+            if self.peek() == Some("layout") {
+                self.position += 1;
+                row_major = self.layout_qualifiers()?;
+                continue;
+            }
+            let line = self.line();
+            let type_name = self.identifier()?;
+            if MEMBER_QUALIFIERS.contains(&type_name) {
+                continue;
+            }
+            if type_name == "struct" {
+                return Err(self.error("nested struct declarations are not supported"));
+            }
+            let has_matrices = type_name.starts_with("mat") ||
+                               self.unqualified_matrix_structs.contains(&type_name);
+            if has_matrices {
+                match row_major.or(block_row_major) {
+                    Some(true) => {
+                        return Err(ParseError {
+                            line: line,
+                            message: "row-major matrices are not supported".to_string(),
+                        })
+                    }
+                    Some(false) => {}
+                    None => unqualified_matrices = true,
+                }
+            }
+            row_major = None;
+            // `float[3] a` is the same as `float a[3]`
+            let type_dimensions = self.dimensions()?;
+            loop {
+                let name = self.identifier()?;
+                let mut dimensions = self.dimensions()?;
+                dimensions.extend(type_dimensions.iter().cloned());
+                members.push(Member {
+                    type_name: type_name.to_string(),
+                    name: name.to_string(),
+                    dimensions: dimensions,
+                });
+                if self.peek() != Some(",") {
+                    break;
+                }
+                self.position += 1;
+            }
+            self.expect(";")?;
+        }
+        self.position += 1;
+        Ok((members, unqualified_matrices))
+    }
+
+    // `(...)` after `layout`, giving whether it makes matrices row-major if it says either way
+    fn layout_qualifiers(&mut self) -> Result<Option<bool>, ParseError> {
+        self.expect("(")?;
+        let mut row_major = None;
+        while let Some(token) = self.next() {
+            match token.text {
+                ")" => return Ok(row_major),
+                "row_major" => row_major = Some(true),
+                "column_major" => row_major = Some(false),
+                _ => {}
+            }
+        }
+        Err(self.error("expected `)`"))
+    }
+
+    fn dimensions(&mut self) -> Result<Vec<Option<usize>>, ParseError> {
+        let mut dimensions = Vec::new();
+        while self.peek() == Some("[") {
+            self.position += 1;
+            if self.peek() == Some("]") {
+                self.position += 1;
+                dimensions.push(None);
+                continue;
+            }
+            let size = self.next().map(|token| token.text).ok_or(self.error("expected a size"))?;
+            let size = size.parse()
+                .ok()
+                .or_else(|| self.defines.iter().find(|d| d.0 == size).map(|d| d.1))
+                .ok_or(self.error(&format!("array size {} is not a number", size)))?;
+            dimensions.push(Some(size));
+            self.expect("]")?;
+        }
+        Ok(dimensions)
+    }
+}
@@ -0,0 +1,384 @@
+use {LayoutInfo, LoadStructLayout, LoadFieldLayout, LayoutError, LayoutErrorKind, OffsetType, StrideType};
+use type_info::GlslType;
+
+/// An active member of a block as reported by the graphics API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockMember {
+    /// Name relative to the block, e.g. `lights[1].color`. Arrays of basic types are reported
+    /// once with a `[0]` suffix, like `weights[0]`.
+    pub name: String,
+    pub member_type: GlslType,
+    pub offset: OffsetType,
+    /// Number of array elements, 1 for non-arrays and 0 for runtime-sized arrays.
+    pub array_size: usize,
+    pub array_stride: StrideType,
+    pub matrix_stride: StrideType,
+    pub row_major: bool,
+    /// Stride of the top-level array of a buffer block member, e.g. of `particles` for
+    /// `particles[0].color`, and 0 for others. Buffer blocks only list the first element of
+    /// such arrays.
+    pub top_level_array_stride: StrideType,
+}
+
+impl BlockMember {
+    pub fn new(name: &str, member_type: GlslType, offset: OffsetType) -> BlockMember {
+        BlockMember {
+            name: name.to_string(),
+            member_type: member_type,
+            offset: offset,
+            array_size: 1,
+            array_stride: 0,
+            matrix_stride: 0,
+            row_major: false,
+            top_level_array_stride: 0,
+        }
+    }
+}
+
+/// Queries about the blocks of a linked program, e.g. through `glGetActiveUniformsiv` or
+/// `glGetProgramResourceiv`.
+pub trait ProgramIntrospection {
+    fn active_blocks(&self) -> Vec<String>;
+
+    /// Members of `block`, or `None` if the program has no such active block.
+    fn block_members(&self, block: &str) -> Option<Vec<BlockMember>>;
+}
+
+/// `ProgramIntrospection` backed by data given up front, for testing without a GL context.
+#[derive(Debug, Clone, Default)]
+pub struct MockProgram {
+    blocks: Vec<(String, Vec<BlockMember>)>,
+}
+
+impl MockProgram {
+    pub fn new() -> MockProgram {
+        MockProgram::default()
+    }
+
+    pub fn add_block(&mut self, name: &str, members: Vec<BlockMember>) {
+        self.blocks.push((name.to_string(), members));
+    }
+}
+
+impl ProgramIntrospection for MockProgram {
+    fn active_blocks(&self) -> Vec<String> {
+        self.blocks.iter().map(|block| block.0.clone()).collect()
+    }
+
+    fn block_members(&self, block: &str) -> Option<Vec<BlockMember>> {
+        self.blocks.iter().find(|b| b.0 == block).map(|b| b.1.clone())
+    }
+}
+
+struct Table(Vec<(String, Box<LoadFieldLayout>)>);
+
+impl LoadStructLayout for Table {
+    fn get_field_layout(&self, field_name: &str) -> Option<LayoutInfo> {
+        self.0.iter().find(|x| x.0 == field_name).map(|x| x.1.field_layout())
+    }
+
+    fn field_names(&self) -> Vec<&str> {
+        self.0.iter().map(|x| &x.0[..]).collect()
+    }
+}
+
+impl LoadFieldLayout for Table {
+    fn field_layout(&self) -> LayoutInfo {
+        LayoutInfo::StructField(self)
+    }
+}
+
+// A basic member with its type
+struct TypedMember(GlslType, LayoutInfo<'static>);
+
+impl LoadFieldLayout for TypedMember {
+    fn field_layout(&self) -> LayoutInfo {
+        LayoutInfo::Typed(self.0, &self.1)
+    }
+}
+
+// An array with its declared length
+struct WithLength(usize, Box<LoadFieldLayout>);
+
+impl LoadFieldLayout for WithLength {
+    fn field_layout(&self) -> LayoutInfo {
+        LayoutInfo::ArrayLength(self.0, &*self.1)
+    }
+}
+
+struct StructArray {
+    offset: OffsetType,
+    stride: StrideType,
+    element: Table,
+}
+
+impl LoadFieldLayout for StructArray {
+    fn field_layout(&self) -> LayoutInfo {
+        LayoutInfo::StructArrayStrideField(self.offset, self.stride, &self.element)
+    }
+}
+
+struct ArrayOfArrays {
+    offset: OffsetType,
+    stride: StrideType,
+    inner: Box<LoadFieldLayout>,
+}
+
+impl LoadFieldLayout for ArrayOfArrays {
+    fn field_layout(&self) -> LayoutInfo {
+        LayoutInfo::ArrayOfArraysField(self.offset, self.stride, &*self.inner)
+    }
+}
+
+/// Layout of a block built from introspection data, for `DynamicLayout::load_layout`.
+pub struct BlockLayout {
+    root: Table,
+}
+
+impl LoadStructLayout for BlockLayout {
+    fn get_field_layout(&self, field_name: &str) -> Option<LayoutInfo> {
+        self.root.get_field_layout(field_name)
+    }
+
+    fn field_names(&self) -> Vec<&str> {
+        self.root.field_names()
+    }
+}
+
+// Members arranged by their names
+enum Node {
+    Empty,
+    Member(BlockMember, bool),
+    Struct(Vec<(String, Node)>),
+    Array(Vec<Node>),
+}
+
+impl Node {
+    fn min_offset(&self) -> Option<OffsetType> {
+        match *self {
+            Node::Empty => None,
+            Node::Member(ref member, _) => Some(member.offset),
+            Node::Struct(ref fields) => fields.iter().filter_map(|f| f.1.min_offset()).min(),
+            Node::Array(ref elements) => elements.iter().filter_map(|e| e.min_offset()).min(),
+        }
+    }
+
+    fn top_level_array_stride(&self) -> StrideType {
+        match *self {
+            Node::Empty => 0,
+            Node::Member(ref member, _) => member.top_level_array_stride,
+            Node::Struct(ref fields) => fields.first().map_or(0, |f| f.1.top_level_array_stride()),
+            Node::Array(ref elements) => elements.first().map_or(0, |e| e.top_level_array_stride()),
+        }
+    }
+
+    fn is_struct(&self) -> bool {
+        match *self {
+            Node::Struct(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn invalid(path: &str, reason: &str) -> LayoutError {
+    LayoutError::new(LayoutErrorKind::InvalidMember(reason.to_string())).in_field(path)
+}
+
+// Splits `a.b[1][0].c` into `[("a", []), ("b", [1, 0]), ("c", [])]`
+fn parse_name(name: &str) -> Result<Vec<(String, Vec<usize>)>, LayoutError> {
+    name.split('.')
+        .map(|part| {
+            let mut pieces = part.split('[');
+            let ident = pieces.next().unwrap_or("");
+            if ident.is_empty() {
+                return Err(invalid(name, "empty name"));
+            }
+            let indices = pieces.map(|piece| {
+                    if !piece.ends_with(']') {
+                        return Err(invalid(name, "unterminated index"));
+                    }
+                    piece[..piece.len() - 1]
+                        .parse()
+                        .map_err(|_| invalid(name, "index is not a number"))
+                })
+                .collect::<Result<Vec<usize>, LayoutError>>()?;
+            Ok((ident.to_string(), indices))
+        })
+        .collect()
+}
+
+fn insert(fields: &mut Vec<(String, Node)>,
+          segments: &[(String, Vec<usize>)],
+          member: BlockMember)
+          -> Result<(), LayoutError> {
+    let (ref name, ref indices) = segments[0];
+    let is_leaf = segments.len() == 1;
+    let mut indices = &indices[..];
+    // The last index of a leaf is the `[0]` marking an array of a basic type
+    let is_array = is_leaf && !indices.is_empty();
+    if is_array {
+        if indices[indices.len() - 1] != 0 {
+            return Err(invalid(&member.name, "array not reported by its first element"));
+        }
+        indices = &indices[..indices.len() - 1];
+    }
+
+    if !fields.iter().any(|f| f.0 == *name) {
+        fields.push((name.clone(), Node::Empty));
+    }
+    let mut node = &mut fields.iter_mut().find(|f| f.0 == *name).unwrap().1;
+    for &index in indices {
+        if let Node::Empty = *node {
+            *node = Node::Array(Vec::new());
+        }
+        node = match *node {
+            Node::Array(ref mut elements) => {
+                while elements.len() <= index {
+                    elements.push(Node::Empty);
+                }
+                &mut elements[index]
+            }
+            _ => return Err(invalid(&member.name, "conflicts with another member")),
+        };
+    }
+
+    if is_leaf {
+        match *node {
+            Node::Empty => {
+                *node = Node::Member(member, is_array);
+                Ok(())
+            }
+            _ => Err(invalid(&member.name, "conflicts with another member")),
+        }
+    } else {
+        if let Node::Empty = *node {
+            *node = Node::Struct(Vec::new());
+        }
+        match *node {
+            Node::Struct(ref mut fields) => insert(fields, &segments[1..], member),
+            _ => Err(invalid(&member.name, "conflicts with another member")),
+        }
+    }
+}
+
+fn with_length(length: usize, info: Box<LoadFieldLayout>) -> Box<LoadFieldLayout> {
+    if length == 0 {
+        info
+    } else {
+        Box::new(WithLength(length, info))
+    }
+}
+
+fn make_table(fields: &[(String, Node)],
+              base: OffsetType,
+              top_level: bool)
+              -> Result<Table, LayoutError> {
+    let mut table = Vec::with_capacity(fields.len());
+    for &(ref name, ref node) in fields {
+        let info = convert(node, base, top_level).map_err(|e| e.in_field(name))?;
+        table.push((name.clone(), info));
+    }
+    Ok(Table(table))
+}
+
+// Offsets in the output are relative to `base`
+fn convert(node: &Node, base: OffsetType, top_level: bool) -> Result<Box<LoadFieldLayout>, LayoutError> {
+    let overlap = || invalid("", "overlaps the start of its parent");
+    let missing = || invalid("", "array element is missing");
+    match *node {
+        Node::Empty => Err(missing()),
+        Node::Member(ref member, is_array) => {
+            let offset = member.offset.checked_sub(base).ok_or_else(&overlap)?;
+            let is_matrix = member.member_type.is_matrix();
+            // Matrices in this crate are always column-major
+            if is_matrix && member.row_major {
+                return Err(invalid("", "row-major matrices are not supported"));
+            }
+            let info = match (is_array, is_matrix) {
+                (false, false) => LayoutInfo::PrimitiveField(offset),
+                (false, true) => LayoutInfo::ArrayField(offset, member.matrix_stride),
+                (true, false) => LayoutInfo::ArrayField(offset, member.array_stride),
+                (true, true) => {
+                    LayoutInfo::MatrixArrayField(offset, member.array_stride, member.matrix_stride)
+                }
+            };
+            let typed = Box::new(TypedMember(member.member_type, info));
+            Ok(if is_array {
+                with_length(member.array_size, typed)
+            } else {
+                typed
+            })
+        }
+        Node::Struct(ref fields) => Ok(Box::new(make_table(fields, base, false)?)),
+        Node::Array(ref elements) => {
+            // The elements have the same layout relative to their starts, so the first one
+            // stands for all of them
+            let first = elements.first().ok_or_else(|| invalid("", "empty array"))?;
+            let start = first.min_offset().ok_or_else(&missing)?;
+            let (stride, length) = match elements.get(1) {
+                Some(second) => {
+                    let stride = second.min_offset()
+                        .ok_or_else(|| missing().in_element(1))?
+                        .checked_sub(start)
+                        .ok_or_else(|| invalid("", "elements are out of order"))?;
+                    (stride, elements.len())
+                }
+                // Only the first element of a top-level array in a buffer block is listed, its
+                // length is not known
+                None if top_level && first.top_level_array_stride() != 0 => {
+                    (first.top_level_array_stride(), 0)
+                }
+                None => (0, 1),
+            };
+            for (i, element) in elements.iter().enumerate() {
+                let expected = start as usize + i * stride as usize;
+                let error = match element.min_offset() {
+                    None => missing(),
+                    Some(_) if element.is_struct() != first.is_struct() => {
+                        invalid("", "conflicts with another member")
+                    }
+                    Some(offset) if offset as usize != expected => {
+                        invalid("", "elements are not evenly spaced")
+                    }
+                    Some(_) => continue,
+                };
+                return Err(error.in_element(i));
+            }
+            let offset = start.checked_sub(base).ok_or_else(&overlap)?;
+            let array: Box<LoadFieldLayout> = match *first {
+                Node::Struct(ref fields) => {
+                    Box::new(StructArray {
+                        offset: offset,
+                        stride: stride,
+                        element: make_table(fields, start, false).map_err(|e| e.in_element(0))?,
+                    })
+                }
+                _ => {
+                    Box::new(ArrayOfArrays {
+                        offset: offset,
+                        stride: stride,
+                        inner: convert(first, start, false).map_err(|e| e.in_element(0))?,
+                    })
+                }
+            };
+            Ok(with_length(length, array))
+        }
+    }
+}
+
+/// Builds the layout of `block` from what `program` reports about it.
+///
+/// Fails if the block does not exist, a member name cannot be parsed, or a member cannot be
+/// represented, such as a row-major matrix.
+pub fn block_layout<P>(program: &P, block: &str) -> Result<BlockLayout, LayoutError>
+    where P: ProgramIntrospection + ?Sized
+{
+    let members = program.block_members(block)
+        .ok_or(LayoutError::new(LayoutErrorKind::MissingBlock(block.to_string())))?;
+    let mut fields = Vec::new();
+    for member in members {
+        let segments = parse_name(&member.name)?;
+        insert(&mut fields, &segments, member)?;
+    }
+    Ok(BlockLayout { root: make_table(&fields, 0, true)? })
+}
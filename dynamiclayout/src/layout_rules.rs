@@ -0,0 +1,352 @@
+use type_info::{TypeInfo, StructInfo};
+
+/// A set of rules (such as std140) that decides where the members of a block are placed.
+pub trait LayoutRules {
+    fn alignment(&self, ty: &TypeInfo) -> usize;
+
+    /// Size of the type, including any padding the rules add at its end.
+    fn size(&self, ty: &TypeInfo) -> usize;
+
+    /// Offset of a member of type `ty` that follows a member ending at `offset`.
+    fn place(&self, offset: usize, ty: &TypeInfo) -> usize {
+        round_up(offset, self.alignment(ty))
+    }
+
+    /// Offsets of the given members, in order, and the total size of the struct they form.
+    fn member_offsets(&self, members: &[&TypeInfo]) -> (Vec<usize>, usize) {
+        let mut offsets = Vec::with_capacity(members.len());
+        let mut end = 0;
+        for ty in members {
+            let offset = self.place(end, ty);
+            offsets.push(offset);
+            end = offset + self.size(ty);
+        }
+        let alignment = self.struct_alignment(members);
+        (offsets, round_up(end, alignment))
+    }
+
+    fn struct_alignment(&self, members: &[&TypeInfo]) -> usize {
+        members.iter().map(|ty| self.alignment(ty)).max().unwrap_or(1)
+    }
+
+    fn struct_offsets(&self, info: &StructInfo) -> (Vec<usize>, usize) {
+        self.member_offsets(&field_types(info))
+    }
+
+    /// Distance between consecutive elements of an array of `element`.
+    fn array_stride(&self, element: &TypeInfo) -> usize {
+        round_up(self.size(element), self.alignment(element))
+    }
+
+    /// Distance between the columns of the matrix type `ty`.
+    fn matrix_stride(&self, _ty: &TypeInfo) -> usize {
+        16
+    }
+}
+
+/// The OpenGL std140 layout rules.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Std140;
+
+impl LayoutRules for Std140 {
+    fn alignment(&self, ty: &TypeInfo) -> usize {
+        ConstRules::Std140.alignment(ty)
+    }
+
+    fn size(&self, ty: &TypeInfo) -> usize {
+        ConstRules::Std140.size(ty)
+    }
+
+    fn struct_alignment(&self, members: &[&TypeInfo]) -> usize {
+        let alignment = members.iter().map(|ty| self.alignment(ty)).max().unwrap_or(1);
+        round_up(alignment, 16)
+    }
+
+    fn array_stride(&self, element: &TypeInfo) -> usize {
+        ConstRules::Std140.array_stride(element)
+    }
+}
+
+/// The OpenGL std430 layout rules of shader storage blocks. Unlike std140, arrays and structs
+/// are only aligned like their members, and matrices with two rows have 8-byte columns.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Std430;
+
+impl LayoutRules for Std430 {
+    fn alignment(&self, ty: &TypeInfo) -> usize {
+        ConstRules::Std430.alignment(ty)
+    }
+
+    fn size(&self, ty: &TypeInfo) -> usize {
+        ConstRules::Std430.size(ty)
+    }
+
+    fn array_stride(&self, element: &TypeInfo) -> usize {
+        ConstRules::Std430.array_stride(element)
+    }
+
+    fn matrix_stride(&self, ty: &TypeInfo) -> usize {
+        ConstRules::Std430.matrix_stride(ty)
+    }
+}
+
+/// Layout rules as `const fn`s, so that derived structs can have their layout computed at
+/// compile time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConstRules {
+    Std140,
+    Std430,
+}
+
+impl ConstRules {
+    pub const fn alignment(self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) => 4,
+            TypeInfo::Vector(_, 2) |
+            TypeInfo::PackedVector(_, 2) => 8,
+            TypeInfo::Vector(..) |
+            TypeInfo::PackedVector(..) => 16,
+            TypeInfo::Matrix { .. } => self.matrix_stride(ty),
+            TypeInfo::Array(element, _) |
+            TypeInfo::RuntimeArray(element) => self.round_std140(self.alignment(element)),
+            TypeInfo::Struct(info) => self.struct_alignment(info),
+        }
+    }
+
+    pub const fn size(self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) => 4,
+            TypeInfo::Vector(_, count) |
+            TypeInfo::PackedVector(_, count) => 4 * count,
+            // Matrices are stored like arrays of column vectors
+            TypeInfo::Matrix { columns, .. } => self.matrix_stride(ty) * columns,
+            TypeInfo::Array(element, len) => self.array_stride(element) * len,
+            TypeInfo::RuntimeArray(_) => 0,
+            TypeInfo::Struct(info) => self.struct_size(info),
+        }
+    }
+
+    pub const fn array_stride(self, element: &TypeInfo) -> usize {
+        round_up(self.size(element), self.round_std140(self.alignment(element)))
+    }
+
+    /// Distance between the columns of the matrix type `ty`.
+    pub const fn matrix_stride(self, ty: &TypeInfo) -> usize {
+        match (self, *ty) {
+            (ConstRules::Std430, TypeInfo::Matrix { rows: 2, .. }) => 8,
+            _ => 16,
+        }
+    }
+
+    /// Offsets of the `N` fields of the struct type `ty`, in order.
+    pub const fn offsets<const N: usize>(self, ty: &TypeInfo) -> [usize; N] {
+        let info = match *ty {
+            TypeInfo::Struct(info) => info,
+            _ => panic!("Offsets can only be computed for structs"),
+        };
+        if info.fields.len() != N {
+            panic!("Wrong number of fields");
+        }
+        let mut offsets = [0; N];
+        let mut end = 0;
+        let mut i = 0;
+        while i < N {
+            let ty = info.fields[i].ty;
+            offsets[i] = round_up(end, self.alignment(ty));
+            end = offsets[i] + self.size(ty);
+            i += 1;
+        }
+        offsets
+    }
+
+    // std140 pads arrays and structs to multiples of 16 bytes
+    const fn round_std140(self, alignment: usize) -> usize {
+        match self {
+            ConstRules::Std140 => round_up(alignment, 16),
+            ConstRules::Std430 => alignment,
+        }
+    }
+
+    const fn struct_alignment(self, info: &StructInfo) -> usize {
+        let mut alignment = 1;
+        let mut i = 0;
+        while i < info.fields.len() {
+            let field_alignment = self.alignment(info.fields[i].ty);
+            if field_alignment > alignment {
+                alignment = field_alignment;
+            }
+            i += 1;
+        }
+        self.round_std140(alignment)
+    }
+
+    const fn struct_size(self, info: &StructInfo) -> usize {
+        let mut end = 0;
+        let mut i = 0;
+        while i < info.fields.len() {
+            let ty = info.fields[i].ty;
+            end = round_up(end, self.alignment(ty)) + self.size(ty);
+            i += 1;
+        }
+        round_up(end, self.struct_alignment(info))
+    }
+}
+
+/// HLSL constant buffer packing. Members are packed into 16-byte registers and may not straddle
+/// two of them. Structs, matrices and array elements start at a new register, but their last
+/// register is not padded, so following members can share it.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct HlslCbuffer;
+
+impl LayoutRules for HlslCbuffer {
+    fn alignment(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) | TypeInfo::Vector(..) | TypeInfo::PackedVector(..) => 4,
+            _ => 16,
+        }
+    }
+
+    fn size(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) => 4,
+            TypeInfo::Vector(_, count) |
+            TypeInfo::PackedVector(_, count) => 4 * count,
+            TypeInfo::Matrix { columns, rows } => 16 * (columns - 1) + 4 * rows,
+            TypeInfo::Array(_, 0) |
+            TypeInfo::RuntimeArray(_) => 0,
+            TypeInfo::Array(element, len) => {
+                self.array_stride(element) * (len - 1) + self.size(element)
+            }
+            TypeInfo::Struct(info) => self.struct_offsets(info).1,
+        }
+    }
+
+    fn place(&self, offset: usize, ty: &TypeInfo) -> usize {
+        let aligned = round_up(offset, self.alignment(ty));
+        match *ty {
+            TypeInfo::Scalar(_) | TypeInfo::Vector(..) | TypeInfo::PackedVector(..) if aligned % 16 + self.size(ty) > 16 => {
+                round_up(offset, 16)
+            }
+            _ => aligned,
+        }
+    }
+
+    // The size of a struct is not rounded up
+    fn member_offsets(&self, members: &[&TypeInfo]) -> (Vec<usize>, usize) {
+        let mut offsets = Vec::with_capacity(members.len());
+        let mut end = 0;
+        for ty in members {
+            let offset = self.place(end, ty);
+            offsets.push(offset);
+            end = offset + self.size(ty);
+        }
+        (offsets, end)
+    }
+
+    fn struct_alignment(&self, _members: &[&TypeInfo]) -> usize {
+        16
+    }
+
+    fn array_stride(&self, element: &TypeInfo) -> usize {
+        round_up(self.size(element), 16)
+    }
+}
+
+pub fn field_types(info: &StructInfo) -> Vec<&'static TypeInfo> {
+    info.fields.iter().map(|f| f.ty).collect()
+}
+
+pub const fn round_up(value: usize, alignment: usize) -> usize {
+    if alignment <= 1 {
+        value
+    } else {
+        (value + alignment - 1) / alignment * alignment
+    }
+}
+
+/// WGSL host-shareable layout, used by uniform and storage buffers in WebGPU. A vec3 is aligned
+/// like a vec4 but only 12 bytes big, and structs are rounded up to their alignment.
+///
+/// The extra uniform buffer constraints, such as 16-byte array strides, are not applied: shaders
+/// violating them do not compile.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Wgsl;
+
+impl LayoutRules for Wgsl {
+    fn alignment(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) => 4,
+            TypeInfo::Vector(_, 2) |
+            TypeInfo::PackedVector(_, 2) => 8,
+            TypeInfo::Vector(..) |
+            TypeInfo::PackedVector(..) => 16,
+            TypeInfo::Matrix { .. } => self.matrix_stride(ty),
+            TypeInfo::Array(element, _) |
+            TypeInfo::RuntimeArray(element) => self.alignment(element),
+            TypeInfo::Struct(info) => self.struct_alignment(&field_types(info)),
+        }
+    }
+
+    fn size(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) => 4,
+            TypeInfo::Vector(_, count) |
+            TypeInfo::PackedVector(_, count) => 4 * count,
+            TypeInfo::Matrix { columns, .. } => self.matrix_stride(ty) * columns,
+            TypeInfo::Array(element, len) => self.array_stride(element) * len,
+            TypeInfo::RuntimeArray(_) => 0,
+            TypeInfo::Struct(info) => self.struct_offsets(info).1,
+        }
+    }
+
+    // Columns are aligned like vectors of their size
+    fn matrix_stride(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Matrix { rows: 2, .. } => 8,
+            _ => 16,
+        }
+    }
+}
+
+/// Metal Shading Language buffer layout. A `float3` is 16 bytes big and aligned like a `float4`,
+/// a `packed_float3` is 12 bytes big and aligned like a `float`.
+///
+/// Packing is chosen per member by its Rust type: declare the members SPIRV-Cross makes
+/// `packed_float3` as `PackedVec3`, the others as `Vec3`. Matrix columns are never packed.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Msl;
+
+impl LayoutRules for Msl {
+    fn alignment(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) |
+            TypeInfo::PackedVector(..) => 4,
+            TypeInfo::Vector(_, 2) => 8,
+            TypeInfo::Vector(_, _) => 16,
+            TypeInfo::Matrix { .. } => self.matrix_stride(ty),
+            TypeInfo::Array(element, _) |
+            TypeInfo::RuntimeArray(element) => self.alignment(element),
+            TypeInfo::Struct(info) => self.struct_alignment(&field_types(info)),
+        }
+    }
+
+    fn size(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Scalar(_) => 4,
+            TypeInfo::Vector(_, 3) => 16,
+            TypeInfo::Vector(_, count) |
+            TypeInfo::PackedVector(_, count) => 4 * count,
+            TypeInfo::Matrix { columns, .. } => self.matrix_stride(ty) * columns,
+            TypeInfo::Array(element, len) => self.array_stride(element) * len,
+            TypeInfo::RuntimeArray(_) => 0,
+            TypeInfo::Struct(info) => self.struct_offsets(info).1,
+        }
+    }
+
+    fn matrix_stride(&self, ty: &TypeInfo) -> usize {
+        match *ty {
+            TypeInfo::Matrix { rows: 2, .. } => 8,
+            _ => 16,
+        }
+    }
+}
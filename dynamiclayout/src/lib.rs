@@ -1,14 +1,36 @@
 
-pub type OffsetType = u16;
-pub type StrideType = u16;
-pub type LengthType = u16;
+pub type OffsetType = u32;
+pub type StrideType = u32;
+pub type LengthType = u32;
 
 pub mod primitive_types;
 pub mod vector_types;
 pub mod matrix_types;
+pub mod upload_ranges;
+pub mod validation;
+pub mod type_info;
+pub mod layout_rules;
+pub mod padding_report;
+pub mod struct_array;
+pub mod nested_array;
+pub mod unsized_array;
+pub mod dynamic_buffer;
+pub mod diff;
+pub mod block_arena;
+pub mod introspection;
+pub mod error;
+pub mod field_matching;
+pub mod glsl;
+pub mod computed_layout;
+pub mod wgsl;
+pub mod static_layout;
+pub mod reflection;
 
 pub use vector_types::*;
 pub use matrix_types::*;
+pub use error::{LayoutError, LayoutErrorKind, ParseError};
+
+use type_info::{GlslType, TypeInfo};
 
 #[derive(Copy, Clone)]
 pub enum LayoutInfo<'a> {
@@ -17,11 +39,163 @@ pub enum LayoutInfo<'a> {
     MatrixArrayField(OffsetType, StrideType, StrideType),
     StructField(&'a LoadStructLayout),
     StructArrayField(&'a [&'a LoadStructLayout]),
+    /// Offset and stride of the array, and the layout of a single element relative to its start.
+    StructArrayStrideField(OffsetType, StrideType, &'a LoadStructLayout),
+    /// Offset and stride of the outer array, and the layout of the inner array relative to the
+    /// start of an outer element.
+    ArrayOfArraysField(OffsetType, StrideType, &'a LoadFieldLayout),
+    /// Element count the shader declares for the wrapped array layout.
+    ArrayLength(usize, &'a LoadFieldLayout),
+    /// Type the shader declares for the wrapped primitive or matrix layout, or for the elements
+    /// of an array of them.
+    Typed(GlslType, &'a LoadFieldLayout),
+}
+
+/// The layout a `LayoutInfo` wraps. Layouts that own their data implement it to make the
+/// wrapped layout on demand, borrowing from themselves.
+pub trait LoadFieldLayout {
+    fn field_layout(&self) -> LayoutInfo;
+}
+
+impl<'a> LoadFieldLayout for LayoutInfo<'a> {
+    fn field_layout(&self) -> LayoutInfo {
+        *self
+    }
+}
+
+impl<'a> LayoutInfo<'a> {
+    /// Strips a possible `ArrayLength` and returns the inner layout and the number of elements
+    /// to use for an array that is declared with `len` elements on the Rust side.
+    pub fn resolve_array_length(self,
+                                len: usize,
+                                options: &LoadOptions)
+                                -> Result<(LayoutInfo<'a>, usize), LayoutError> {
+        match self {
+            LayoutInfo::ArrayLength(declared, inner) => {
+                Ok((inner.field_layout(), options.array_length.resolve(declared, len)?))
+            }
+            other => Ok((other, len)),
+        }
+    }
+
+    /// Strips a possible `Typed`, also from under an `ArrayLength`, and returns the type.
+    pub fn strip_type(self) -> (LayoutInfo<'a>, Option<GlslType>) {
+        match self {
+            LayoutInfo::Typed(ty, inner) => (inner.field_layout(), Some(ty)),
+            LayoutInfo::ArrayLength(len, inner) => {
+                match inner.field_layout() {
+                    LayoutInfo::Typed(ty, inner) => (LayoutInfo::ArrayLength(len, inner), Some(ty)),
+                    _ => (self, None),
+                }
+            }
+            other => (other, None),
+        }
+    }
+
+    /// Fails if the layout has a type tag that does not match the type `info`.
+    pub fn check_type(self, info: &TypeInfo) -> Result<LayoutInfo<'a>, LayoutError> {
+        let (layout, found) = self.strip_type();
+        match (found, GlslType::from_type_info(info)) {
+            (Some(found), Some(expected)) if found != expected => {
+                Err(LayoutErrorKind::TypeMismatch {
+                        expected: expected,
+                        found: found,
+                    }
+                    .into())
+            }
+            _ => Ok(layout),
+        }
+    }
+
+    /// Name of the kind of layout for error messages.
+    pub fn kind_name(&self) -> &'static str {
+        match *self {
+            LayoutInfo::PrimitiveField(..) => "primitive",
+            LayoutInfo::ArrayField(..) => "array",
+            LayoutInfo::MatrixArrayField(..) => "matrix array",
+            LayoutInfo::StructField(..) => "struct",
+            LayoutInfo::StructArrayField(..) => "struct array",
+            LayoutInfo::StructArrayStrideField(..) => "strided struct array",
+            LayoutInfo::ArrayOfArraysField(..) => "array of arrays",
+            LayoutInfo::ArrayLength(_, inner) |
+            LayoutInfo::Typed(_, inner) => inner.field_layout().kind_name(),
+        }
+    }
+
+    /// Error for a layout that is not of the `expected` kind.
+    pub fn unexpected(&self, expected: &'static str) -> LayoutError {
+        LayoutErrorKind::UnexpectedLayout {
+                expected: expected,
+                found: self.kind_name(),
+            }
+            .into()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArrayLengthPolicy {
+    /// Array lengths must match exactly.
+    Exact,
+    /// Only the elements present on both sides are used.
+    Truncate,
+}
+
+impl Default for ArrayLengthPolicy {
+    fn default() -> ArrayLengthPolicy {
+        ArrayLengthPolicy::Exact
+    }
+}
+
+impl ArrayLengthPolicy {
+    /// Element count to use when the shader declares `declared` elements and Rust `len`.
+    pub fn resolve(self, declared: usize, len: usize) -> Result<usize, LayoutError> {
+        if declared == len {
+            return Ok(len);
+        }
+        match self {
+            ArrayLengthPolicy::Exact => {
+                Err(LayoutErrorKind::ArrayLengthMismatch {
+                        declared: declared,
+                        len: len,
+                    }
+                    .into())
+            }
+            ArrayLengthPolicy::Truncate => Ok(::std::cmp::min(declared, len)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldMatching {
+    /// Every Rust field must be in the layout, members only the shader declares are ignored.
+    Required,
+    /// The Rust struct and the layout must have exactly the same fields.
+    Strict,
+    /// Rust fields the layout lacks are absent: they take no space, and writing and reading
+    /// values skips them. Accessors of structs with absent fields panic.
+    Lenient,
+}
+
+impl Default for FieldMatching {
+    fn default() -> FieldMatching {
+        FieldMatching::Required
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct LoadOptions {
+    pub array_length: ArrayLengthPolicy,
+    pub fields: FieldMatching,
 }
 
 
 pub trait LoadStructLayout {
     fn get_field_layout(&self, field_name: &str) -> Option<LayoutInfo>;
+
+    /// Names of all fields in the layout, for reporting the ones a Rust struct does not use.
+    fn field_names(&self) -> Vec<&str> {
+        Vec::new()
+    }
 }
 
 impl<'a> LoadStructLayout for LayoutInfo<'a> {
@@ -31,12 +205,23 @@ impl<'a> LoadStructLayout for LayoutInfo<'a> {
             _ => None,
         }
     }
+
+    fn field_names(&self) -> Vec<&str> {
+        match *self {
+            LayoutInfo::StructField(ref inner) => inner.field_names(),
+            _ => Vec::new(),
+        }
+    }
 }
 
 impl<'a> LoadStructLayout for &'a [(&'a str, ::LayoutInfo<'a>)] {
     fn get_field_layout(&self, field_name: &str) -> Option<LayoutInfo> {
         self.iter().find(|x| x.0 == field_name).map(|x| x.1)
     }
+
+    fn field_names(&self) -> Vec<&str> {
+        self.iter().map(|x| x.0).collect()
+    }
 }
 
 
@@ -55,12 +240,14 @@ impl SimpleFieldLayout {
 pub struct ArrayFieldLayout {
     offset: OffsetType,
     stride: StrideType,
+    length: usize,
 }
 
 impl ArrayFieldLayout {
     pub unsafe fn offset_ptr(&self, ptr: *mut u8, index: usize) -> *mut u8 {
-        let total_offset: isize = self.offset as isize + self.stride as isize * index as isize;
-        ptr.offset(total_offset)
+        let total_offset = element_offset(self.offset, self.stride, index)
+            .expect("Array element offset overflows OffsetType");
+        ptr.offset(total_offset as isize)
     }
 }
 
@@ -69,39 +256,126 @@ pub struct MatrixArrayFieldLayout {
     offset: OffsetType,
     array_stride: StrideType,
     matrix_stride: StrideType,
+    length: usize,
 }
 
 
-#[derive(Debug)]
+/// `offset + stride * index`, or `None` if it does not fit in an `OffsetType`.
+pub fn element_offset(offset: OffsetType, stride: StrideType, index: usize) -> Option<OffsetType> {
+    if index > OffsetType::max_value() as usize {
+        return None;
+    }
+    (stride as OffsetType).checked_mul(index as OffsetType).and_then(|o| o.checked_add(offset))
+}
+
+/// Checks that `count` elements of `size` bytes placed `stride` bytes apart starting from
+/// `offset` end within the range of `OffsetType`.
+pub fn check_extent(offset: OffsetType,
+                    stride: StrideType,
+                    count: usize,
+                    size: usize)
+                    -> Result<(), LayoutError> {
+    if count == 0 {
+        return Ok(());
+    }
+    if size > LengthType::max_value() as usize {
+        return Err(LayoutErrorKind::OffsetOverflow.into());
+    }
+    element_offset(offset, stride, count - 1)
+        .and_then(|last| last.checked_add(size as OffsetType))
+        .map(|_| ())
+        .ok_or(LayoutErrorKind::OffsetOverflow.into())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct FieldSpan {
     pub offset: OffsetType,
     pub length: LengthType,
 }
 
+/// A `FieldSpan` with the path of its field (e.g. `compound.matrix[2]`) and the leaf type name.
+#[derive(Debug)]
+pub struct NamedFieldSpan {
+    pub path: String,
+    pub type_name: &'static str,
+    pub scalar_type: type_info::ScalarType,
+    /// Alignment the offset must satisfy, i.e. the size of the leaf type's components.
+    pub alignment: LengthType,
+    pub span: FieldSpan,
+}
+
+/// Number of bytes from the start of the block up to the end of its last field. This is only a
+/// lower bound of the block's data size, which includes any padding after the last field.
+pub fn layout_size<T: LayoutDynamicField>(layout: &T::Layout) -> usize {
+    T::get_field_spans(layout)
+        .map(|span| span.offset as usize + span.length as usize)
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn join_field_path(parent: &str, field: &str) -> String {
+    if parent.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", parent, field)
+    }
+}
+
+pub fn index_field_path(parent: &str, index: usize) -> String {
+    format!("{}[{}]", parent, index)
+}
+
 pub trait DynamicLayout : LayoutDynamicField {
-    fn load_layout(layout_info: &LoadStructLayout) -> Result<Self::Layout, ()>;
+    fn load_layout(layout_info: &LoadStructLayout) -> Result<Self::Layout, LayoutError> {
+        Self::load_layout_with_options(layout_info, &LoadOptions::default())
+    }
+
+    fn load_layout_with_options(layout_info: &LoadStructLayout,
+                                options: &LoadOptions)
+                                -> Result<Self::Layout, LayoutError>;
 }
 
 pub trait LayoutDynamicField {
     type Layout;
 
-    fn make_layout(layout_field: LayoutInfo) -> Result<Self::Layout, ()>;
+    fn make_layout(layout_field: LayoutInfo, options: &LoadOptions) -> Result<Self::Layout, LayoutError>;
 
     fn get_field_spans(layout: &Self::Layout) -> Box<Iterator<Item = FieldSpan>>;
+
+    /// `path` is the path of the field itself; pass "" for the top level struct.
+    fn get_named_field_spans(layout: &Self::Layout, path: &str) -> Box<Iterator<Item = NamedFieldSpan>>;
 }
 
 pub trait LayoutArrayDynamicField {
     type Layout;
 
-    fn make_layout(layout_field: LayoutInfo, len: usize) -> Result<Self::Layout, ()>;
+    fn make_layout(layout_field: LayoutInfo,
+                   len: usize,
+                   options: &LoadOptions)
+                   -> Result<Self::Layout, LayoutError>;
+
+    /// Number of elements the layout was made for, which may be less than the declared length
+    /// of the Rust array if it was truncated.
+    fn len(layout: &Self::Layout) -> usize;
 
     fn get_field_spans(layout: &Self::Layout, len: usize) -> Box<Iterator<Item = FieldSpan>>;
+
+    fn get_named_field_spans(layout: &Self::Layout,
+                             path: &str,
+                             len: usize)
+                             -> Box<Iterator<Item = NamedFieldSpan>>;
 }
 
+// In the accessor constructors `size` is the number of bytes available starting from `bytes`.
+// Only runtime-sized arrays need it, everything else is expected to fit.
+
 pub trait AccessDynamicField<'a>: LayoutDynamicField {
     type Accessor: 'a;
 
-    unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8) -> Self::Accessor;
+    unsafe fn accessor_from_layout(layout: &'a Self::Layout,
+                                   bytes: *mut u8,
+                                   size: usize)
+                                   -> Self::Accessor;
 }
 
 pub trait AccessArrayDynamicField<'a>: LayoutArrayDynamicField {
@@ -109,6 +383,24 @@ pub trait AccessArrayDynamicField<'a>: LayoutArrayDynamicField {
 
     unsafe fn accessor_from_layout(layout: &'a Self::Layout,
                                    bytes: *mut u8,
+                                   size: usize,
                                    len: usize)
                                    -> Self::Accessor;
 }
+
+// Copying whole values between Rust types and buffers. All-zero bytes must be a valid value of
+// implementing types, they are used for the parts of a value a layout does not cover.
+
+pub unsafe trait CopyDynamicField: LayoutDynamicField {
+    unsafe fn write_field(layout: &Self::Layout, bytes: *mut u8, value: &Self);
+
+    unsafe fn read_field(layout: &Self::Layout, bytes: *const u8, out: &mut Self);
+}
+
+pub unsafe trait CopyArrayDynamicField: LayoutArrayDynamicField + Sized {
+    /// Writes the first `len(layout)` elements of `values`.
+    unsafe fn write_array(layout: &Self::Layout, bytes: *mut u8, values: &[Self]);
+
+    /// Reads `len(layout)` elements into the start of `out`.
+    unsafe fn read_array(layout: &Self::Layout, bytes: *const u8, out: &mut [Self]);
+}
@@ -1,7 +1,154 @@
 
+use std::marker::PhantomData;
+use std::ptr;
 use std::ops::{Index, IndexMut};
-use {LayoutInfo, ArrayFieldLayout, MatrixArrayFieldLayout, LayoutDynamicField, AccessDynamicField,
-     FieldSpan, OffsetType, LengthType, LayoutArrayDynamicField, AccessArrayDynamicField};
+use {LayoutInfo, LoadOptions, ArrayFieldLayout, MatrixArrayFieldLayout, LayoutDynamicField, AccessDynamicField,
+     FieldSpan, NamedFieldSpan, OffsetType, StrideType, LengthType, LayoutArrayDynamicField,
+     AccessArrayDynamicField, CopyDynamicField, CopyArrayDynamicField, LayoutError, LayoutErrorKind, element_offset,
+     check_extent};
+use type_info::{ScalarType, DescribeType};
+use static_layout::AccessStaticField;
+use layout_rules::ConstRules;
+
+// Offset of column `column` of matrix `index` in a matrix array
+fn matrix_column_offset(offset: OffsetType,
+                        array_stride: StrideType,
+                        matrix_stride: StrideType,
+                        index: usize,
+                        column: usize)
+                        -> Option<OffsetType> {
+    element_offset(offset, array_stride, index)
+        .and_then(|matrix_offset| element_offset(matrix_offset, matrix_stride, column))
+}
+
+impl MatrixArrayFieldLayout {
+    /// Layout of matrix `index` of the array as if it was a single matrix with `columns` columns.
+    fn matrix_layout(&self, index: usize, columns: usize) -> ArrayFieldLayout {
+        ArrayFieldLayout {
+            offset: element_offset(self.offset, self.array_stride, index)
+                .expect("Matrix array element offset overflows OffsetType"),
+            stride: self.matrix_stride,
+            length: columns,
+        }
+    }
+}
+
+/// Creates the accessor of a single matrix from a pointer to its first column.
+pub trait MatrixColumns: for<'b> AccessDynamicField<'b> {
+    unsafe fn column_accessor<'b>(bytes: *mut u8, matrix_stride: StrideType) -> <Self as AccessDynamicField<'b>>::Accessor;
+}
+
+/// Accessor for an array of matrices that creates the accessors of the elements on demand.
+pub struct MatrixArrayAccessor<'a, M: 'a> {
+    bytes: *mut u8,
+    array_stride: StrideType,
+    matrix_stride: StrideType,
+    length: usize,
+    phantom: PhantomData<&'a mut M>,
+}
+
+impl<'a, M: MatrixColumns + 'a> MatrixArrayAccessor<'a, M> {
+    /// `bytes` points to the first matrix of the array.
+    pub unsafe fn new(bytes: *mut u8,
+                      array_stride: StrideType,
+                      matrix_stride: StrideType,
+                      len: usize)
+                      -> MatrixArrayAccessor<'a, M> {
+        MatrixArrayAccessor {
+            bytes: bytes,
+            array_stride: array_stride,
+            matrix_stride: matrix_stride,
+            length: len,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get<'b>(&'b mut self, index: usize) -> Option<<M as AccessDynamicField<'b>>::Accessor> {
+        if index >= self.length {
+            return None;
+        }
+        let offset = element_offset(0, self.array_stride, index)
+            .expect("Matrix array element offset overflows OffsetType");
+        Some(unsafe { M::column_accessor(self.bytes.offset(offset as isize), self.matrix_stride) })
+    }
+
+    pub fn index<'b>(&'b mut self, index: usize) -> <M as AccessDynamicField<'b>>::Accessor {
+        let length = self.length;
+        match self.get(index) {
+            Some(accessor) => accessor,
+            None => {
+                panic!("MatrixArrayAccessor index out of bounds: the len is {} but the index is {}",
+                       length,
+                       index)
+            }
+        }
+    }
+
+    pub fn iter_mut<'b>(&'b mut self) -> MatrixArrayIterMut<'b, M> {
+        MatrixArrayIterMut {
+            bytes: self.bytes,
+            array_stride: self.array_stride,
+            matrix_stride: self.matrix_stride,
+            front: 0,
+            back: self.length,
+            phantom: PhantomData,
+        }
+    }
+}
+
+pub struct MatrixArrayIterMut<'b, M: 'b> {
+    bytes: *mut u8,
+    array_stride: StrideType,
+    matrix_stride: StrideType,
+    front: usize,
+    back: usize,
+    phantom: PhantomData<&'b mut M>,
+}
+
+impl<'b, M: MatrixColumns + 'b> MatrixArrayIterMut<'b, M> {
+    fn element(&self, index: usize) -> <M as AccessDynamicField<'b>>::Accessor {
+        let offset = element_offset(0, self.array_stride, index)
+            .expect("Matrix array element offset overflows OffsetType");
+        unsafe { M::column_accessor(self.bytes.offset(offset as isize), self.matrix_stride) }
+    }
+}
+
+impl<'b, M: MatrixColumns + 'b> Iterator for MatrixArrayIterMut<'b, M> {
+    type Item = <M as AccessDynamicField<'b>>::Accessor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        Some(self.element(self.front - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'b, M: MatrixColumns + 'b> DoubleEndedIterator for MatrixArrayIterMut<'b, M> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(self.element(self.back))
+    }
+}
+
+impl<'b, M: MatrixColumns + 'b> ExactSizeIterator for MatrixArrayIterMut<'b, M> {}
 
 macro_rules! make_matrix_type {
     ($matrix_type:ident [$column_count:expr][$row_count:expr] $($field:expr),+) => (
@@ -14,6 +161,10 @@ macro_rules! make_matrix_type {
                 $matrix_type(data)
             }
 
+            pub fn columns(&self) -> [[f32; $row_count]; $column_count] {
+                self.0
+            }
+
             // TODO: Make sure this actually does what it should
             unsafe fn accessor_from_layout<'a, 'b>(layout: &'a <Self as LayoutDynamicField>::Layout, bytes: *mut u8) -> <Self as AccessDynamicField<'b>>::Accessor {
                 [
@@ -22,28 +173,38 @@ macro_rules! make_matrix_type {
             }
         }
 
+        // Indexing borrows a column, so the matrix has to be 4-byte aligned. Use `columns` for
+        // matrices in packed structs or read from buffers.
         impl Index<usize> for $matrix_type {
             type Output = [f32; $row_count];
 
             fn index(&self, index: usize) -> &Self::Output {
-                &self.0[index]
+                let columns = unsafe {
+                    &*(self as *const Self as *const [[f32; $row_count]; $column_count])
+                };
+                &columns[index]
             }
         }
 
         impl IndexMut<usize> for $matrix_type {
             fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-                &mut self.0[index]
+                let columns = unsafe {
+                    &mut *(self as *mut Self as *mut [[f32; $row_count]; $column_count])
+                };
+                &mut columns[index]
             }
         }
 
         impl LayoutDynamicField for $matrix_type {
             type Layout = ArrayFieldLayout;
 
-            fn make_layout(layout_field: ::LayoutInfo) -> Result<Self::Layout, ()> {
+            fn make_layout(layout_field: ::LayoutInfo, _: &LoadOptions) -> Result<Self::Layout, LayoutError> {
+                let layout_field = layout_field.check_type(&<$matrix_type as DescribeType>::TYPE_INFO)?;
                 if let ::LayoutInfo::ArrayField (offset, stride) = layout_field {
-                    Ok(ArrayFieldLayout { offset: offset, stride: stride })
+                    check_extent(offset, stride, $column_count, ::std::mem::size_of::<[f32; $row_count]>())?;
+                    Ok(ArrayFieldLayout { offset: offset, stride: stride, length: $column_count })
                 } else {
-                    Err(())
+                    Err(layout_field.unexpected("array"))
                 }
             }
 
@@ -52,58 +213,151 @@ macro_rules! make_matrix_type {
                 let stride = layout.stride;
                 // TODO: 0..4 vs. 0..$column_count
                 Box::new((0..$column_count).map(move |i| FieldSpan {
-                    offset: (offset + stride * i) as OffsetType,
+                    offset: element_offset(offset, stride, i).expect("Field offset overflows OffsetType"),
                     length: (::std::mem::size_of::<f32>() * $row_count) as LengthType,
                 }))
             }
+
+            fn get_named_field_spans(layout: &Self::Layout, path: &str) -> Box<Iterator<Item=NamedFieldSpan>> {
+                let offset = layout.offset;
+                let stride = layout.stride;
+                let path = path.to_string();
+                Box::new((0..$column_count).map(move |i| NamedFieldSpan {
+                    path: ::index_field_path(&path, i),
+                    type_name: stringify!($matrix_type),
+                    scalar_type: ScalarType::Float,
+                    alignment: ::std::mem::size_of::<f32>() as LengthType,
+                    span: FieldSpan {
+                        offset: element_offset(offset, stride, i).expect("Field offset overflows OffsetType"),
+                        length: (::std::mem::size_of::<f32>() * $row_count) as LengthType,
+                    },
+                }))
+            }
         }
 
         impl<'a> AccessDynamicField<'a> for $matrix_type {
             type Accessor = [&'a mut [f32; $row_count]; $column_count];
 
-            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8) -> Self::Accessor {
+            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, _: usize) -> Self::Accessor {
                 $matrix_type::accessor_from_layout(layout, bytes)
             }
         }
 
+        impl<'a> AccessStaticField<'a> for $matrix_type {
+            type Accessor = [&'a mut [f32; $row_count]; $column_count];
+
+            unsafe fn static_accessor(bytes: *mut u8, _: usize, rules: ConstRules) -> Self::Accessor {
+                let stride = rules.matrix_stride(&<$matrix_type as DescribeType>::TYPE_INFO);
+                let layout = ArrayFieldLayout { offset: 0, stride: stride as StrideType, length: $column_count };
+                $matrix_type::accessor_from_layout(&layout, bytes)
+            }
+        }
+
         impl LayoutArrayDynamicField for $matrix_type {
             type Layout = MatrixArrayFieldLayout;
 
-            fn make_layout(layout_field: LayoutInfo, _: usize) -> Result<Self::Layout, ()> {
+            fn make_layout(layout_field: LayoutInfo, len: usize, options: &LoadOptions) -> Result<Self::Layout, LayoutError> {
+                let layout_field = layout_field.check_type(&<$matrix_type as DescribeType>::TYPE_INFO)?;
+                let (layout_field, len) = layout_field.resolve_array_length(len, options)?;
                 if let LayoutInfo::MatrixArrayField(offset, array_stride, matrix_stride) = layout_field {
-                    Ok(MatrixArrayFieldLayout { offset: offset, array_stride: array_stride, matrix_stride: matrix_stride })
+                    if len > 0 {
+                        let last_matrix = element_offset(offset, array_stride, len - 1)
+                            .ok_or(LayoutError::new(LayoutErrorKind::OffsetOverflow))?;
+                        check_extent(last_matrix, matrix_stride, $column_count, ::std::mem::size_of::<[f32; $row_count]>())?;
+                    }
+                    Ok(MatrixArrayFieldLayout { offset: offset, array_stride: array_stride, matrix_stride: matrix_stride, length: len })
                 } else {
-                    Err(())
+                    Err(layout_field.unexpected("matrix array"))
                 }
             }
 
+            fn len(layout: &Self::Layout) -> usize {
+                layout.length
+            }
+
             fn get_field_spans(layout: &Self::Layout, len: usize) -> Box<Iterator<Item=FieldSpan>> {
                 let offset = layout.offset;
                 let array_stride = layout.array_stride;
                 let matrix_stride = layout.matrix_stride;
-                Box::new((0..len as u16).flat_map(move |i| (0..$row_count).map(move |r| FieldSpan {
-                    offset: (offset + array_stride * i + matrix_stride * r) as OffsetType,
-                    length: ::std::mem::size_of::<f32>() as LengthType * $column_count as LengthType,
+                Box::new((0..len).flat_map(move |i| (0..$column_count).map(move |c| FieldSpan {
+                    offset: matrix_column_offset(offset, array_stride, matrix_stride, i, c)
+                        .expect("Field offset overflows OffsetType"),
+                    length: ::std::mem::size_of::<f32>() as LengthType * $row_count as LengthType,
                 })))
             }
+
+            fn get_named_field_spans(layout: &Self::Layout, path: &str, len: usize) -> Box<Iterator<Item=NamedFieldSpan>> {
+                let offset = layout.offset;
+                let array_stride = layout.array_stride;
+                let matrix_stride = layout.matrix_stride;
+                let path = path.to_string();
+                Box::new((0..len).flat_map(move |i| {
+                    let matrix_path = ::index_field_path(&path, i);
+                    (0..$column_count).map(move |c| NamedFieldSpan {
+                        path: ::index_field_path(&matrix_path, c),
+                        type_name: stringify!($matrix_type),
+                        scalar_type: ScalarType::Float,
+                        alignment: ::std::mem::size_of::<f32>() as LengthType,
+                        span: FieldSpan {
+                            offset: matrix_column_offset(offset, array_stride, matrix_stride, i, c)
+                                .expect("Field offset overflows OffsetType"),
+                            length: ::std::mem::size_of::<f32>() as LengthType * $row_count as LengthType,
+                        },
+                    })
+                }))
+            }
         }
 
         impl<'a> AccessArrayDynamicField<'a> for $matrix_type {
-            type Accessor = Vec<<$matrix_type as AccessDynamicField<'a>>::Accessor>;
-
-            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, len: usize) -> Self::Accessor {
-                let mut accessor = Vec::with_capacity(len);
-                for i in 0..len {
-                    let offset = (i as OffsetType) * layout.array_stride + layout.offset;
-                    // The pointer given to accessor_from_layout already has the offset calculated, therefore use 0 here
-                    let matrix_layout = ArrayFieldLayout { offset: 0, stride: layout.matrix_stride };
-                    accessor.push($matrix_type::accessor_from_layout(&matrix_layout, bytes.offset(offset as isize)));
+            type Accessor = MatrixArrayAccessor<'a, $matrix_type>;
+
+            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, _: usize, len: usize) -> Self::Accessor {
+                MatrixArrayAccessor::new(bytes.offset(layout.offset as isize),
+                                         layout.array_stride,
+                                         layout.matrix_stride,
+                                         len)
+            }
+        }
+
+        unsafe impl CopyDynamicField for $matrix_type {
+            unsafe fn write_field(layout: &Self::Layout, bytes: *mut u8, value: &Self) {
+                let columns = value.0;
+                for (c, column) in columns.iter().enumerate() {
+                    ptr::write_unaligned(layout.offset_ptr(bytes, c) as *mut [f32; $row_count], *column);
+                }
+            }
+
+            unsafe fn read_field(layout: &Self::Layout, bytes: *const u8, out: &mut Self) {
+                let mut columns = out.0;
+                for (c, column) in columns.iter_mut().enumerate() {
+                    *column = ptr::read_unaligned(layout.offset_ptr(bytes as *mut u8, c) as *const [f32; $row_count]);
                 }
-                accessor
+                out.0 = columns;
             }
         }
 
+        unsafe impl CopyArrayDynamicField for $matrix_type {
+            unsafe fn write_array(layout: &Self::Layout, bytes: *mut u8, values: &[Self]) {
+                for (i, value) in values[..layout.length].iter().enumerate() {
+                    let matrix_layout = layout.matrix_layout(i, $column_count);
+                    <$matrix_type as CopyDynamicField>::write_field(&matrix_layout, bytes, value);
+                }
+            }
+
+            unsafe fn read_array(layout: &Self::Layout, bytes: *const u8, out: &mut [Self]) {
+                for (i, out) in out[..layout.length].iter_mut().enumerate() {
+                    let matrix_layout = layout.matrix_layout(i, $column_count);
+                    <$matrix_type as CopyDynamicField>::read_field(&matrix_layout, bytes, out);
+                }
+            }
+        }
 
+        impl MatrixColumns for $matrix_type {
+            unsafe fn column_accessor<'b>(bytes: *mut u8, matrix_stride: StrideType) -> <Self as AccessDynamicField<'b>>::Accessor {
+                let matrix_layout = ArrayFieldLayout { offset: 0, stride: matrix_stride, length: $column_count };
+                $matrix_type::accessor_from_layout(&matrix_layout, bytes)
+            }
+        }
     );
 }
 
@@ -0,0 +1,227 @@
+use std::cell::OnceCell;
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+use {LayoutInfo, LoadOptions, LayoutArrayDynamicField, AccessArrayDynamicField, CopyArrayDynamicField, FieldSpan,
+     NamedFieldSpan, OffsetType, StrideType, LayoutError, element_offset, check_extent};
+
+/// Layout of an array whose elements are arrays themselves, such as `[[f32; 4]; 8]`.
+pub struct NestedArrayLayout<L> {
+    offset: OffsetType,
+    stride: StrideType,
+    length: usize,
+    /// Layout of the inner array, relative to the start of an element of the outer array.
+    inner: L,
+}
+
+impl<L> NestedArrayLayout<L> {
+    fn element_offset(&self, index: usize) -> OffsetType {
+        element_offset(self.offset, self.stride, index)
+            .expect("Array element offset overflows OffsetType")
+    }
+}
+
+impl<T: LayoutArrayDynamicField, const N: usize> LayoutArrayDynamicField for [T; N] {
+    type Layout = NestedArrayLayout<T::Layout>;
+
+    fn make_layout(layout_field: LayoutInfo,
+                   len: usize,
+                   options: &LoadOptions)
+                   -> Result<Self::Layout, LayoutError> {
+        let (layout_field, len) = layout_field.resolve_array_length(len, options)?;
+        if let LayoutInfo::ArrayOfArraysField(offset, stride, inner) = layout_field {
+            let inner = T::make_layout(inner.field_layout(), N, options)?;
+            let element_size = T::get_field_spans(&inner, T::len(&inner))
+                .map(|span| span.offset as usize + span.length as usize)
+                .max()
+                .unwrap_or(0);
+            check_extent(offset, stride, len, element_size)?;
+            Ok(NestedArrayLayout {
+                offset: offset,
+                stride: stride,
+                length: len,
+                inner: inner,
+            })
+        } else {
+            Err(layout_field.unexpected("array of arrays"))
+        }
+    }
+
+    fn len(layout: &Self::Layout) -> usize {
+        layout.length
+    }
+
+    fn get_field_spans(layout: &Self::Layout, len: usize) -> Box<Iterator<Item = FieldSpan>> {
+        let mut spans = Vec::new();
+        for i in 0..len {
+            let base = layout.element_offset(i);
+            spans.extend(T::get_field_spans(&layout.inner, T::len(&layout.inner)).map(|span| {
+                FieldSpan {
+                    offset: base.checked_add(span.offset).expect("Field offset overflows OffsetType"),
+                    length: span.length,
+                }
+            }));
+        }
+        Box::new(spans.into_iter())
+    }
+
+    fn get_named_field_spans(layout: &Self::Layout,
+                             path: &str,
+                             len: usize)
+                             -> Box<Iterator<Item = NamedFieldSpan>> {
+        let mut spans = Vec::new();
+        for i in 0..len {
+            let base = layout.element_offset(i);
+            let element_path = ::index_field_path(path, i);
+            let inner_len = T::len(&layout.inner);
+            spans.extend(T::get_named_field_spans(&layout.inner, &element_path, inner_len).map(|mut named| {
+                named.span.offset = base.checked_add(named.span.offset)
+                    .expect("Field offset overflows OffsetType");
+                named
+            }));
+        }
+        Box::new(spans.into_iter())
+    }
+}
+
+impl<'a, T: AccessArrayDynamicField<'a> + 'a, const N: usize> AccessArrayDynamicField<'a> for [T; N]
+    where T::Layout: 'a
+{
+    type Accessor = NestedArrayAccessor<'a, T>;
+
+    unsafe fn accessor_from_layout(layout: &'a Self::Layout,
+                                   bytes: *mut u8,
+                                   size: usize,
+                                   len: usize)
+                                   -> Self::Accessor {
+        NestedArrayAccessor::new(layout, bytes, size, len)
+    }
+}
+
+/// Accessor for an array of arrays. The accessors of the inner arrays are created on first use
+/// and kept, so that `acc.grid[i][j]` can borrow them.
+pub struct NestedArrayAccessor<'a, T: AccessArrayDynamicField<'a> + 'a> {
+    layout: &'a NestedArrayLayout<T::Layout>,
+    bytes: *mut u8,
+    size: usize,
+    length: usize,
+    // Allocated on first use as well
+    elements: OnceCell<Box<[OnceCell<T::Accessor>]>>,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: AccessArrayDynamicField<'a> + 'a> NestedArrayAccessor<'a, T> {
+    pub unsafe fn new(layout: &'a NestedArrayLayout<T::Layout>,
+                      bytes: *mut u8,
+                      size: usize,
+                      len: usize)
+                      -> NestedArrayAccessor<'a, T> {
+        NestedArrayAccessor {
+            layout: layout,
+            bytes: bytes,
+            size: size,
+            length: len,
+            elements: OnceCell::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get(&mut self, index: usize) -> Option<&mut T::Accessor> {
+        if index >= self.length {
+            return None;
+        }
+        self.element(index);
+        self.elements.get_mut().and_then(|elements| elements[index].get_mut())
+    }
+
+    pub fn iter_mut(&mut self) -> NestedArrayIterMut<'_, 'a, T> {
+        for i in 0..self.length {
+            self.element(i);
+        }
+        let elements: &mut [OnceCell<T::Accessor>] = match self.elements.get_mut() {
+            Some(elements) => elements,
+            None => &mut [],
+        };
+        NestedArrayIterMut { elements: elements.iter_mut() }
+    }
+
+    fn elements(&self) -> &[OnceCell<T::Accessor>] {
+        self.elements.get_or_init(|| (0..self.length).map(|_| OnceCell::new()).collect())
+    }
+
+    // The accessor of element `index`, created if needed
+    fn element(&self, index: usize) -> &T::Accessor {
+        if index >= self.length {
+            panic!("NestedArrayAccessor index out of bounds: the len is {} but the index is {}",
+                   self.length,
+                   index);
+        }
+        self.elements()[index].get_or_init(|| unsafe {
+            let offset = self.layout.element_offset(index) as usize;
+            T::accessor_from_layout(&self.layout.inner,
+                                    self.bytes.add(offset),
+                                    self.size.saturating_sub(offset),
+                                    T::len(&self.layout.inner))
+        })
+    }
+}
+
+impl<'a, T: AccessArrayDynamicField<'a> + 'a> Index<usize> for NestedArrayAccessor<'a, T> {
+    type Output = T::Accessor;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.element(index)
+    }
+}
+
+impl<'a, T: AccessArrayDynamicField<'a> + 'a> IndexMut<usize> for NestedArrayAccessor<'a, T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.element(index);
+        self.elements.get_mut().and_then(|elements| elements[index].get_mut()).unwrap()
+    }
+}
+
+pub struct NestedArrayIterMut<'b, 'a: 'b, T: AccessArrayDynamicField<'a> + 'a> {
+    elements: ::std::slice::IterMut<'b, OnceCell<T::Accessor>>,
+}
+
+impl<'b, 'a: 'b, T: AccessArrayDynamicField<'a> + 'a> Iterator for NestedArrayIterMut<'b, 'a, T> {
+    type Item = &'b mut T::Accessor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.elements.next().and_then(|element| element.get_mut())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elements.size_hint()
+    }
+}
+
+impl<'b, 'a: 'b, T: AccessArrayDynamicField<'a> + 'a> DoubleEndedIterator for NestedArrayIterMut<'b, 'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.elements.next_back().and_then(|element| element.get_mut())
+    }
+}
+
+unsafe impl<T: CopyArrayDynamicField, const N: usize> CopyArrayDynamicField for [T; N] {
+    unsafe fn write_array(layout: &Self::Layout, bytes: *mut u8, values: &[Self]) {
+        for (i, value) in values[..layout.length].iter().enumerate() {
+            let offset = layout.element_offset(i);
+            T::write_array(&layout.inner, bytes.offset(offset as isize), &value[..]);
+        }
+    }
+
+    unsafe fn read_array(layout: &Self::Layout, bytes: *const u8, out: &mut [Self]) {
+        for (i, out) in out[..layout.length].iter_mut().enumerate() {
+            let offset = layout.element_offset(i);
+            T::read_array(&layout.inner, bytes.offset(offset as isize), &mut out[..]);
+        }
+    }
+}
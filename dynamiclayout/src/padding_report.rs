@@ -0,0 +1,141 @@
+use {LayoutError, LayoutErrorKind};
+use type_info::{DescribeType, TypeInfo, StructInfo};
+use layout_rules::{LayoutRules, field_types};
+
+// Orders of up to this many fields are searched exhaustively, bigger structs are sorted greedily.
+const EXHAUSTIVE_SEARCH_LIMIT: usize = 8;
+
+#[derive(Debug)]
+pub struct FieldPadding {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    /// Bytes skipped to align the field.
+    pub padding_before: usize,
+    /// Bytes inside the field that hold no data, e.g. array elements rounded up to 16 bytes.
+    pub internal_padding: usize,
+}
+
+impl FieldPadding {
+    pub fn wasted(&self) -> usize {
+        self.padding_before + self.internal_padding
+    }
+}
+
+#[derive(Debug)]
+pub struct PaddingReport {
+    pub fields: Vec<FieldPadding>,
+    /// Bytes added after the last field to round the block up to its alignment.
+    pub trailing_padding: usize,
+    pub size: usize,
+    /// Field names in an order that gives the smallest block found.
+    pub suggested_order: Vec<&'static str>,
+    pub suggested_size: usize,
+}
+
+impl PaddingReport {
+    pub fn wasted(&self) -> usize {
+        self.fields.iter().map(FieldPadding::wasted).sum::<usize>() + self.trailing_padding
+    }
+}
+
+/// Fails if `T` is not a struct.
+pub fn padding_report<T: DescribeType, R: LayoutRules>(rules: &R) -> Result<PaddingReport, LayoutError> {
+    match T::TYPE_INFO {
+        TypeInfo::Struct(info) => Ok(struct_padding_report(info, rules)),
+        _ => Err(LayoutErrorKind::UnsupportedType.into()),
+    }
+}
+
+pub fn struct_padding_report<R: LayoutRules>(info: &StructInfo, rules: &R) -> PaddingReport {
+    let types = field_types(info);
+    let (offsets, size) = rules.member_offsets(&types);
+
+    let mut fields = Vec::with_capacity(info.fields.len());
+    let mut end = 0;
+    for (field, &offset) in info.fields.iter().zip(offsets.iter()) {
+        let field_size = rules.size(field.ty);
+        fields.push(FieldPadding {
+            name: field.name,
+            offset: offset,
+            size: field_size,
+            padding_before: offset - end,
+            internal_padding: field_size - field.ty.data_size(),
+        });
+        end = offset + field_size;
+    }
+
+    // A runtime-sized array has to stay the last member, so only the other fields are reordered
+    let (movable, pinned): (Vec<usize>, Vec<usize>) = (0..types.len()).partition(|&i| match *types[i] {
+        TypeInfo::RuntimeArray(_) => false,
+        _ => true,
+    });
+    let movable_types: Vec<&TypeInfo> = movable.iter().map(|&i| types[i]).collect();
+    let pinned_types: Vec<&TypeInfo> = pinned.iter().map(|&i| types[i]).collect();
+    let order = if movable_types.len() <= EXHAUSTIVE_SEARCH_LIMIT {
+        best_order(&movable_types, &pinned_types, rules)
+    } else {
+        greedy_order(&movable_types, rules)
+    };
+    let order: Vec<usize> = order.into_iter().map(|i| movable[i]).chain(pinned).collect();
+    let ordered_types: Vec<&TypeInfo> = order.iter().map(|&i| types[i]).collect();
+    let (_, suggested_size) = rules.member_offsets(&ordered_types);
+
+    PaddingReport {
+        fields: fields,
+        trailing_padding: size - end,
+        size: size,
+        suggested_order: order.iter().map(|&i| info.fields[i].name).collect(),
+        suggested_size: suggested_size,
+    }
+}
+
+// `tail` follows the permuted fields in every order.
+fn best_order<R: LayoutRules>(types: &[&TypeInfo], tail: &[&TypeInfo], rules: &R) -> Vec<usize> {
+    let ordered_size = |order: &[usize]| {
+        let ordered: Vec<&TypeInfo> = order.iter().map(|&i| types[i]).chain(tail.iter().cloned()).collect();
+        rules.member_offsets(&ordered).1
+    };
+    let mut order: Vec<usize> = (0..types.len()).collect();
+    let mut best = (ordered_size(&order), order.clone());
+    permute(&mut order, 0, &mut |order| {
+        let size = ordered_size(order);
+        if size < best.0 {
+            best = (size, order.to_vec());
+        }
+    });
+    best.1
+}
+
+fn permute<F: FnMut(&[usize])>(order: &mut Vec<usize>, start: usize, visit: &mut F) {
+    if start == order.len() {
+        visit(order);
+        return;
+    }
+    for i in start..order.len() {
+        order.swap(start, i);
+        permute(order, start + 1, visit);
+        order.swap(start, i);
+    }
+}
+
+// Repeatedly appends the field that needs the least padding, preferring more strictly aligned
+// fields on ties so that small fields are left to fill the gaps.
+fn greedy_order<R: LayoutRules>(types: &[&TypeInfo], rules: &R) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..types.len()).collect();
+    let mut order = Vec::with_capacity(types.len());
+    let mut end = 0;
+    while !remaining.is_empty() {
+        let (position, _) = remaining.iter()
+            .enumerate()
+            .min_by_key(|&(_, &i)| {
+                let padding = rules.place(end, types[i]) - end;
+                (padding, ::std::usize::MAX - rules.alignment(types[i]), i)
+            })
+            .unwrap();
+        let chosen = remaining.remove(position);
+        end = rules.place(end, types[chosen]) + rules.size(types[chosen]);
+        order.push(chosen);
+    }
+    order
+}
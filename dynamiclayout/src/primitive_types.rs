@@ -1,10 +1,14 @@
 
 use std::marker::PhantomData;
-use std::ops::{Index, IndexMut};
-use {FieldSpan, LayoutInfo, SimpleFieldLayout, ArrayFieldLayout, LayoutDynamicField,
-     AccessDynamicField, LengthType, OffsetType, StrideType, LayoutArrayDynamicField,
-     AccessArrayDynamicField};
+use std::ptr;
+use std::ops::{Index, IndexMut, RangeBounds, Bound};
+use {FieldSpan, NamedFieldSpan, LayoutInfo, LoadOptions, SimpleFieldLayout, ArrayFieldLayout, LayoutDynamicField,
+     AccessDynamicField, LengthType, StrideType, LayoutArrayDynamicField, AccessArrayDynamicField,
+     CopyDynamicField, CopyArrayDynamicField, LayoutError, element_offset, check_extent};
 use vector_types::*;
+use type_info::{Scalar, DescribeType};
+use static_layout::AccessStaticField;
+use layout_rules::ConstRules;
 
 pub struct PrimitiveArrayAccessor<'a, T: 'a> {
     pub bytes: *mut u8,
@@ -20,10 +24,196 @@ impl<'a, T: 'a> PrimitiveArrayAccessor<'a, T> {
                    self.length,
                    index);
         }
-        unsafe { self.bytes.offset(index as isize * self.stride as isize) as *mut T }
+        element_ptr(self.bytes, self.stride, index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.length {
+            Some(unsafe { &*self.index(index) })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.length {
+            Some(unsafe { &mut *self.index(index) })
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        Iter {
+            bytes: self.bytes,
+            stride: self.stride,
+            front: 0,
+            back: self.length,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<T> {
+        IterMut {
+            bytes: self.bytes,
+            stride: self.stride,
+            front: 0,
+            back: self.length,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Read-only view of the elements in `range`.
+    ///
+    /// Panics if the range is out of bounds, like slicing does.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> PrimitiveArraySlice<T> {
+        let (start, end) = self.range_bounds(range);
+        PrimitiveArraySlice {
+            bytes: element_ptr(self.bytes, self.stride, start) as *mut u8,
+            stride: self.stride,
+            length: end - start,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Accessor for the elements in `range`, borrowing this one mutably.
+    ///
+    /// Panics if the range is out of bounds, like slicing does.
+    pub fn slice_mut<R: RangeBounds<usize>>(&mut self, range: R) -> PrimitiveArrayAccessor<T> {
+        let (start, end) = self.range_bounds(range);
+        PrimitiveArrayAccessor {
+            bytes: element_ptr(self.bytes, self.stride, start) as *mut u8,
+            stride: self.stride,
+            length: end - start,
+            phantom: PhantomData,
+        }
+    }
+
+    fn range_bounds<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.length,
+        };
+        if start > end || end > self.length {
+            panic!("PrimitiveArrayAccessor range {}..{} out of bounds for len {}",
+                   start,
+                   end,
+                   self.length);
+        }
+        (start, end)
+    }
+}
+
+/// Read-only view of a range of a `PrimitiveArrayAccessor`, from `slice`.
+pub struct PrimitiveArraySlice<'a, T: 'a> {
+    bytes: *mut u8,
+    stride: StrideType,
+    length: usize,
+    phantom: PhantomData<&'a T>,
+}
+
+impl<'a, T: 'a> PrimitiveArraySlice<'a, T> {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.length {
+            Some(unsafe { &*element_ptr(self.bytes, self.stride, index) })
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        Iter {
+            bytes: self.bytes,
+            stride: self.stride,
+            front: 0,
+            back: self.length,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a> PrimitiveArraySlice<'a, T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
     }
 }
 
+impl<'a, T: 'a> Index<usize> for PrimitiveArraySlice<'a, T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.get(index) {
+            Some(element) => element,
+            None => {
+                panic!("PrimitiveArraySlice index out of bounds: the len is {} but the index is {}",
+                       self.length,
+                       index)
+            }
+        }
+    }
+}
+
+impl<'a, 'b, T: 'a> IntoIterator for &'b PrimitiveArraySlice<'a, T> {
+    type Item = &'b T;
+    type IntoIter = Iter<'b, T>;
+
+    fn into_iter(self) -> Iter<'b, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T: Copy + 'a> PrimitiveArrayAccessor<'a, T> {
+    /// Copies all elements from `src`, which must have the same length as the array.
+    pub fn copy_from_slice(&mut self, src: &[T]) {
+        if src.len() != self.length {
+            panic!("PrimitiveArrayAccessor length mismatch: the len is {} but the source len is {}",
+                   self.length,
+                   src.len());
+        }
+        for (dst, value) in self.iter_mut().zip(src) {
+            *dst = *value;
+        }
+    }
+
+    pub fn fill(&mut self, value: T) {
+        for dst in self.iter_mut() {
+            *dst = value;
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+fn element_ptr<T>(bytes: *mut u8, stride: StrideType, index: usize) -> *mut T {
+    let offset = element_offset(0, stride, index)
+        .expect("PrimitiveArrayAccessor element offset overflows OffsetType");
+    unsafe { bytes.offset(offset as isize) as *mut T }
+}
+
 impl<'a, T: 'a> Index<usize> for PrimitiveArrayAccessor<'a, T> {
     type Output = T;
 
@@ -38,17 +228,91 @@ impl<'a, T: 'a> IndexMut<usize> for PrimitiveArrayAccessor<'a, T> {
     }
 }
 
+impl<'a, 'b, T: 'a> IntoIterator for &'b PrimitiveArrayAccessor<'a, T> {
+    type Item = &'b T;
+    type IntoIter = Iter<'b, T>;
+
+    fn into_iter(self) -> Iter<'b, T> {
+        self.iter()
+    }
+}
+
+impl<'a, 'b, T: 'a> IntoIterator for &'b mut PrimitiveArrayAccessor<'a, T> {
+    type Item = &'b mut T;
+    type IntoIter = IterMut<'b, T>;
+
+    fn into_iter(self) -> IterMut<'b, T> {
+        self.iter_mut()
+    }
+}
+
+macro_rules! impl_strided_iter {
+    ($name:ident, $item:ty, $deref:ident) => (
+        pub struct $name<'a, T: 'a> {
+            bytes: *mut u8,
+            stride: StrideType,
+            front: usize,
+            back: usize,
+            phantom: PhantomData<$item>,
+        }
+
+        impl<'a, T: 'a> Iterator for $name<'a, T> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<$item> {
+                if self.front == self.back {
+                    return None;
+                }
+                let ptr = element_ptr::<T>(self.bytes, self.stride, self.front);
+                self.front += 1;
+                Some(unsafe { $deref!(ptr) })
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let remaining = self.back - self.front;
+                (remaining, Some(remaining))
+            }
+        }
+
+        impl<'a, T: 'a> DoubleEndedIterator for $name<'a, T> {
+            fn next_back(&mut self) -> Option<$item> {
+                if self.front == self.back {
+                    return None;
+                }
+                self.back -= 1;
+                let ptr = element_ptr::<T>(self.bytes, self.stride, self.back);
+                Some(unsafe { $deref!(ptr) })
+            }
+        }
+
+        impl<'a, T: 'a> ExactSizeIterator for $name<'a, T> {}
+    )
+}
+
+macro_rules! deref_ref {
+    ($ptr:expr) => (&*$ptr)
+}
+
+macro_rules! deref_mut {
+    ($ptr:expr) => (&mut *$ptr)
+}
+
+impl_strided_iter!(Iter, &'a T, deref_ref);
+impl_strided_iter!(IterMut, &'a mut T, deref_mut);
+
 macro_rules! impl_primitive_type {
 
-    ($primitive_type:ty) => (
+    ($primitive_type:ty : $component_type:ty) => (
         impl LayoutDynamicField for $primitive_type {
             type Layout = SimpleFieldLayout;
 
-            fn make_layout(layout_field: LayoutInfo) -> Result<Self::Layout, ()> {
+            fn make_layout(layout_field: LayoutInfo, _: &LoadOptions) -> Result<Self::Layout, LayoutError> {
+                let layout_field = layout_field.check_type(&<$primitive_type as DescribeType>::TYPE_INFO)?;
                 if let LayoutInfo::PrimitiveField(offset) = layout_field {
+                    check_extent(offset, 0, 1, ::std::mem::size_of::<$primitive_type>())?;
                     Ok(SimpleFieldLayout { offset: offset })
                 } else {
-                    Err(())
+                    Err(layout_field.unexpected("primitive"))
                 }
             }
 
@@ -59,41 +323,110 @@ macro_rules! impl_primitive_type {
                 };
                 Box::new(Some(span).into_iter())
             }
+
+            fn get_named_field_spans(layout: &Self::Layout, path: &str) -> Box<Iterator<Item=NamedFieldSpan>> {
+                let span = NamedFieldSpan {
+                    path: path.to_string(),
+                    type_name: stringify!($primitive_type),
+                    scalar_type: <$component_type as Scalar>::SCALAR_TYPE,
+                    alignment: ::std::mem::size_of::<$component_type>() as LengthType,
+                    span: FieldSpan {
+                        offset: layout.offset,
+                        length: ::std::mem::size_of::<$primitive_type>() as LengthType,
+                    },
+                };
+                Box::new(Some(span).into_iter())
+            }
         }
 
         impl<'a> AccessDynamicField<'a> for $primitive_type {
             type Accessor = &'a mut $primitive_type;
 
-            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8) -> Self::Accessor {
+            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, _: usize) -> Self::Accessor {
                 &mut *(layout.offset_ptr(bytes) as *mut $primitive_type)
             }
         }
 
+        impl<'a> AccessStaticField<'a> for $primitive_type {
+            type Accessor = &'a mut $primitive_type;
+
+            unsafe fn static_accessor(bytes: *mut u8, _: usize, _: ConstRules) -> Self::Accessor {
+                &mut *(bytes as *mut $primitive_type)
+            }
+        }
+
         impl LayoutArrayDynamicField for $primitive_type {
             type Layout = ArrayFieldLayout;
 
-            fn make_layout(layout_field: LayoutInfo, _: usize) -> Result<Self::Layout, ()> {
+            fn make_layout(layout_field: LayoutInfo, len: usize, options: &LoadOptions) -> Result<Self::Layout, LayoutError> {
+                let layout_field = layout_field.check_type(&<$primitive_type as DescribeType>::TYPE_INFO)?;
+                let (layout_field, len) = layout_field.resolve_array_length(len, options)?;
                 if let LayoutInfo::ArrayField(offset, stride) = layout_field {
-                    Ok(ArrayFieldLayout { offset: offset, stride: stride })
+                    check_extent(offset, stride, len, ::std::mem::size_of::<$primitive_type>())?;
+                    Ok(ArrayFieldLayout { offset: offset, stride: stride, length: len })
                 } else {
-                    Err(())
+                    Err(layout_field.unexpected("array"))
                 }
             }
 
+            fn len(layout: &Self::Layout) -> usize {
+                layout.length
+            }
+
             fn get_field_spans(layout: &Self::Layout, len: usize) -> Box<Iterator<Item=FieldSpan>> {
                 let offset = layout.offset;
                 let stride = layout.stride;
-                Box::new((0..len as u16).map(move |i| FieldSpan {
-                    offset: (offset + stride * i) as OffsetType,
+                Box::new((0..len).map(move |i| FieldSpan {
+                    offset: element_offset(offset, stride, i).expect("Field offset overflows OffsetType"),
                     length: ::std::mem::size_of::<$primitive_type>() as LengthType,
                 }))
             }
+
+            fn get_named_field_spans(layout: &Self::Layout, path: &str, len: usize) -> Box<Iterator<Item=NamedFieldSpan>> {
+                let offset = layout.offset;
+                let stride = layout.stride;
+                let path = path.to_string();
+                Box::new((0..len).map(move |i| NamedFieldSpan {
+                    path: ::index_field_path(&path, i),
+                    type_name: stringify!($primitive_type),
+                    scalar_type: <$component_type as Scalar>::SCALAR_TYPE,
+                    alignment: ::std::mem::size_of::<$component_type>() as LengthType,
+                    span: FieldSpan {
+                        offset: element_offset(offset, stride, i).expect("Field offset overflows OffsetType"),
+                        length: ::std::mem::size_of::<$primitive_type>() as LengthType,
+                    },
+                }))
+            }
+        }
+
+        unsafe impl CopyDynamicField for $primitive_type {
+            unsafe fn write_field(layout: &Self::Layout, bytes: *mut u8, value: &Self) {
+                ptr::write_unaligned(layout.offset_ptr(bytes) as *mut $primitive_type, *value);
+            }
+
+            unsafe fn read_field(layout: &Self::Layout, bytes: *const u8, out: &mut Self) {
+                *out = ptr::read_unaligned(layout.offset_ptr(bytes as *mut u8) as *const $primitive_type);
+            }
+        }
+
+        unsafe impl CopyArrayDynamicField for $primitive_type {
+            unsafe fn write_array(layout: &Self::Layout, bytes: *mut u8, values: &[Self]) {
+                for (i, value) in values[..layout.length].iter().enumerate() {
+                    ptr::write_unaligned(layout.offset_ptr(bytes, i) as *mut $primitive_type, *value);
+                }
+            }
+
+            unsafe fn read_array(layout: &Self::Layout, bytes: *const u8, out: &mut [Self]) {
+                for (i, out) in out[..layout.length].iter_mut().enumerate() {
+                    *out = ptr::read_unaligned(layout.offset_ptr(bytes as *mut u8, i) as *const $primitive_type);
+                }
+            }
         }
 
         impl<'a> AccessArrayDynamicField<'a> for $primitive_type {
             type Accessor = PrimitiveArrayAccessor<'a, $primitive_type>;
 
-            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, len: usize) -> Self::Accessor {
+            unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, _: usize, len: usize) -> Self::Accessor {
                 PrimitiveArrayAccessor {
                     bytes: bytes.offset(layout.offset as isize),
                     stride: layout.stride,
@@ -106,18 +439,22 @@ macro_rules! impl_primitive_type {
 
 }
 
-impl_primitive_type!(f32);
-impl_primitive_type!(i32);
-impl_primitive_type!(u32);
+impl_primitive_type!(f32: f32);
+impl_primitive_type!(i32: i32);
+impl_primitive_type!(u32: u32);
+
+impl_primitive_type!(Vec2: f32);
+impl_primitive_type!(IVec2: i32);
+impl_primitive_type!(UVec2: u32);
 
-impl_primitive_type!(Vec2);
-impl_primitive_type!(IVec2);
-impl_primitive_type!(UVec2);
+impl_primitive_type!(Vec3: f32);
+impl_primitive_type!(IVec3: i32);
+impl_primitive_type!(UVec3: u32);
 
-impl_primitive_type!(Vec3);
-impl_primitive_type!(IVec3);
-impl_primitive_type!(UVec3);
+impl_primitive_type!(PackedVec3: f32);
+impl_primitive_type!(PackedIVec3: i32);
+impl_primitive_type!(PackedUVec3: u32);
 
-impl_primitive_type!(Vec4);
-impl_primitive_type!(IVec4);
-impl_primitive_type!(UVec4);
+impl_primitive_type!(Vec4: f32);
+impl_primitive_type!(IVec4: i32);
+impl_primitive_type!(UVec4: u32);
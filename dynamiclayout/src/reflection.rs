@@ -0,0 +1,206 @@
+use {AccessDynamicField, AccessArrayDynamicField, index_field_path};
+use type_info::{TypeInfo, ScalarType};
+use vector_types::*;
+use primitive_types::PrimitiveArrayAccessor;
+use matrix_types::{MatrixArrayAccessor, MatrixColumns};
+use struct_array::StructArrayAccessor;
+use nested_array::NestedArrayAccessor;
+use unsized_array::UnsizedStaticAccessor;
+use static_layout::AccessStaticField;
+
+/// What kind of value a field holds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldKind {
+    Primitive(ScalarType),
+    Vector(ScalarType, usize),
+    Matrix { columns: usize, rows: usize },
+    /// A struct with its name.
+    Struct(&'static str),
+    /// An array with its length, `None` for runtime-sized arrays.
+    Array(Option<usize>),
+}
+
+impl FieldKind {
+    pub const fn of(ty: &TypeInfo) -> FieldKind {
+        match *ty {
+            TypeInfo::Scalar(scalar) => FieldKind::Primitive(scalar),
+            TypeInfo::Vector(scalar, count) |
+            TypeInfo::PackedVector(scalar, count) => FieldKind::Vector(scalar, count),
+            TypeInfo::Matrix { columns, rows } => {
+                FieldKind::Matrix {
+                    columns: columns,
+                    rows: rows,
+                }
+            }
+            TypeInfo::Struct(info) => FieldKind::Struct(info.name),
+            TypeInfo::Array(_, len) => FieldKind::Array(Some(len)),
+            TypeInfo::RuntimeArray(_) => FieldKind::Array(None),
+        }
+    }
+}
+
+/// A field of a struct as listed by `Reflect::FIELDS`.
+#[derive(Debug, Copy, Clone)]
+pub struct ReflectedField {
+    pub name: &'static str,
+    /// The Rust type as written in the struct, e.g. `[Light; 4]`.
+    pub type_name: &'static str,
+    pub kind: FieldKind,
+    pub ty: &'static TypeInfo,
+}
+
+/// Structs that list their fields as static data. Implemented by the derive.
+pub trait Reflect {
+    const FIELDS: &'static [ReflectedField];
+}
+
+/// A scalar, vector or matrix column in a buffer, as given to a `FieldVisitor`.
+#[derive(Debug)]
+pub enum FieldValue<'a> {
+    Float(&'a mut f32),
+    Int(&'a mut i32),
+    Uint(&'a mut u32),
+    Vec2(&'a mut Vec2),
+    Vec3(&'a mut Vec3),
+    Vec4(&'a mut Vec4),
+    IVec2(&'a mut IVec2),
+    IVec3(&'a mut IVec3),
+    IVec4(&'a mut IVec4),
+    UVec2(&'a mut UVec2),
+    UVec3(&'a mut UVec3),
+    UVec4(&'a mut UVec4),
+    /// A column of a matrix with two rows.
+    Column2(&'a mut [f32; 2]),
+    Column3(&'a mut [f32; 3]),
+    Column4(&'a mut [f32; 4]),
+}
+
+pub trait FieldVisitor {
+    /// Called for every scalar, vector and matrix column with its path, e.g. `lights[1].color`
+    /// or `transform[2]`.
+    fn visit(&mut self, path: &str, value: FieldValue);
+}
+
+impl<F: FnMut(&str, FieldValue)> FieldVisitor for F {
+    fn visit(&mut self, path: &str, value: FieldValue) {
+        self(path, value)
+    }
+}
+
+/// Accessors whose fields can be visited without knowing the struct they belong to.
+pub trait VisitFields {
+    /// `path` is the path of the field itself; pass "" for the top level struct.
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor);
+}
+
+/// Types a `FieldValue` can refer to.
+pub trait LeafValue {
+    fn field_value<'a>(&'a mut self) -> FieldValue<'a>;
+}
+
+macro_rules! impl_leaf_value {
+    ($leaf_type:ty, $variant:ident) => (
+        impl LeafValue for $leaf_type {
+            fn field_value<'a>(&'a mut self) -> FieldValue<'a> {
+                FieldValue::$variant(self)
+            }
+        }
+    )
+}
+
+impl_leaf_value!(f32, Float);
+impl_leaf_value!(i32, Int);
+impl_leaf_value!(u32, Uint);
+impl_leaf_value!(Vec2, Vec2);
+impl_leaf_value!(Vec3, Vec3);
+impl_leaf_value!(Vec4, Vec4);
+impl_leaf_value!(IVec2, IVec2);
+impl_leaf_value!(IVec3, IVec3);
+impl_leaf_value!(IVec4, IVec4);
+impl_leaf_value!(UVec2, UVec2);
+impl_leaf_value!(UVec3, UVec3);
+impl_leaf_value!(UVec4, UVec4);
+
+// Packed vectors have the same representation as the unpacked ones
+macro_rules! impl_packed_leaf_value {
+    ($leaf_type:ty, $vector_type:ty, $variant:ident) => (
+        impl LeafValue for $leaf_type {
+            fn field_value<'a>(&'a mut self) -> FieldValue<'a> {
+                FieldValue::$variant(unsafe { &mut *(self as *mut $leaf_type as *mut $vector_type) })
+            }
+        }
+    )
+}
+
+impl_packed_leaf_value!(PackedVec3, Vec3, Vec3);
+impl_packed_leaf_value!(PackedIVec3, IVec3, IVec3);
+impl_packed_leaf_value!(PackedUVec3, UVec3, UVec3);
+impl_leaf_value!([f32; 2], Column2);
+impl_leaf_value!([f32; 3], Column3);
+impl_leaf_value!([f32; 4], Column4);
+
+impl<'a, T: LeafValue> VisitFields for &'a mut T {
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor) {
+        visitor.visit(path, (**self).field_value())
+    }
+}
+
+// Matrix columns and arrays in static accessors
+impl<A: VisitFields, const N: usize> VisitFields for [A; N] {
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor) {
+        for (i, element) in self.iter_mut().enumerate() {
+            element.visit_fields(&index_field_path(path, i), visitor);
+        }
+    }
+}
+
+impl<'a, T> VisitFields for NestedArrayAccessor<'a, T>
+    where T: AccessArrayDynamicField<'a> + 'a,
+          T::Accessor: VisitFields
+{
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor) {
+        for (i, element) in self.iter_mut().enumerate() {
+            element.visit_fields(&index_field_path(path, i), visitor);
+        }
+    }
+}
+
+impl<'a, T: LeafValue + 'a> VisitFields for PrimitiveArrayAccessor<'a, T> {
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor) {
+        for (i, element) in self.iter_mut().enumerate() {
+            visitor.visit(&index_field_path(path, i), element.field_value());
+        }
+    }
+}
+
+impl<'a, M: MatrixColumns + 'a> VisitFields for MatrixArrayAccessor<'a, M>
+    where for<'b> <M as AccessDynamicField<'b>>::Accessor: VisitFields
+{
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor) {
+        for i in 0..self.len() {
+            self.index(i).visit_fields(&index_field_path(path, i), visitor);
+        }
+    }
+}
+
+impl<'a, T> VisitFields for StructArrayAccessor<'a, T>
+    where T: for<'b> AccessDynamicField<'b> + 'a,
+          for<'b> <T as AccessDynamicField<'b>>::Accessor: VisitFields
+{
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor) {
+        for i in 0..self.len() {
+            self.index(i).visit_fields(&index_field_path(path, i), visitor);
+        }
+    }
+}
+
+impl<'a, T> VisitFields for UnsizedStaticAccessor<'a, T>
+    where T: for<'b> AccessStaticField<'b> + 'a,
+          for<'b> <T as AccessStaticField<'b>>::Accessor: VisitFields
+{
+    fn visit_fields(&mut self, path: &str, visitor: &mut FieldVisitor) {
+        for i in 0..self.len() {
+            self.index(i).visit_fields(&index_field_path(path, i), visitor);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use type_info::DescribeType;
+use layout_rules::ConstRules;
+
+/// Access to a field whose layout is known at compile time, without loading a layout.
+///
+/// `#[dynamiclayout(std140)]` or `#[dynamiclayout(std430)]` on a derived struct `Foo` adds the
+/// constants `Foo::RULES`, `Foo::OFFSETS` and `Foo::SIZE`, and `Foo::static_accessor`, which
+/// returns a `FooStaticAccessor`. Fields of struct types need one of the attributes too, but are
+/// laid out by the rules of the struct that contains them. Other rules, such as HLSL or Metal
+/// ones, are only available through the dynamic `load_layout` path.
+pub trait AccessStaticField<'a> {
+    type Accessor: 'a;
+
+    /// `bytes` points to the start of the field and `size` bytes from it must be valid for the
+    /// lifetime `'a`. Only runtime-sized arrays look at `size`, the other fields fit in it.
+    unsafe fn static_accessor(bytes: *mut u8, size: usize, rules: ConstRules) -> Self::Accessor;
+}
+
+impl<'a, T, const N: usize> AccessStaticField<'a> for [T; N]
+    where T: AccessStaticField<'a> + DescribeType
+{
+    type Accessor = [T::Accessor; N];
+
+    unsafe fn static_accessor(bytes: *mut u8, size: usize, rules: ConstRules) -> Self::Accessor {
+        let stride = rules.array_stride(&T::TYPE_INFO);
+        ::std::array::from_fn(|i| unsafe {
+            T::static_accessor(bytes.add(i * stride), size.saturating_sub(i * stride), rules)
+        })
+    }
+}
@@ -0,0 +1,234 @@
+use std::marker::PhantomData;
+use {LayoutInfo, LoadOptions, LayoutDynamicField, AccessDynamicField, CopyDynamicField, LayoutError, FieldSpan, NamedFieldSpan, OffsetType,
+     StrideType, element_offset, check_extent};
+
+pub enum StructArrayLayout<L> {
+    /// One layout per element, with offsets relative to the start of the block.
+    Explicit(Vec<L>),
+    /// A single layout shared by all elements, with offsets relative to the start of an element.
+    Strided {
+        offset: OffsetType,
+        stride: StrideType,
+        length: usize,
+        element: L,
+    },
+}
+
+impl<L> StructArrayLayout<L> {
+    pub fn make<T>(layout_field: LayoutInfo, len: usize, options: &LoadOptions) -> Result<Self, LayoutError>
+        where T: LayoutDynamicField<Layout = L>
+    {
+        let (layout_field, len) = layout_field.resolve_array_length(len, options)?;
+        match layout_field {
+            LayoutInfo::StructArrayField(layouts) => {
+                let len = options.array_length.resolve(layouts.len(), len)?;
+                let mut output = Vec::with_capacity(len);
+                for (i, input) in layouts[..len].iter().enumerate() {
+                    output.push(T::make_layout(LayoutInfo::StructField(*input), options)
+                        .map_err(|e| e.in_element(i))?);
+                }
+                Ok(StructArrayLayout::Explicit(output))
+            }
+            LayoutInfo::StructArrayStrideField(offset, stride, element) => {
+                let element = T::make_layout(LayoutInfo::StructField(element), options)?;
+                let element_size = T::get_field_spans(&element)
+                    .map(|span| span.offset as usize + span.length as usize)
+                    .max()
+                    .unwrap_or(0);
+                check_extent(offset, stride, len, element_size)?;
+                Ok(StructArrayLayout::Strided {
+                    offset: offset,
+                    stride: stride,
+                    length: len,
+                    element: element,
+                })
+            }
+            other => Err(other.unexpected("struct array")),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match *self {
+            StructArrayLayout::Explicit(ref layouts) => layouts.len(),
+            StructArrayLayout::Strided { length, .. } => length,
+        }
+    }
+
+    /// Layout of an element and the offset its field offsets are relative to.
+    pub fn element(&self, index: usize) -> Option<(&L, OffsetType)> {
+        match *self {
+            StructArrayLayout::Explicit(ref layouts) => layouts.get(index).map(|l| (l, 0)),
+            StructArrayLayout::Strided { offset, stride, ref element, .. } => {
+                element_offset(offset, stride, index).map(|base| (element, base))
+            }
+        }
+    }
+
+    pub fn field_spans<T>(&self, len: usize) -> Box<Iterator<Item = FieldSpan>>
+        where T: LayoutDynamicField<Layout = L>
+    {
+        let mut spans = Vec::new();
+        for i in 0..len {
+            let (layout, base) = self.element(i).expect("Struct array element offset overflows OffsetType");
+            spans.extend(T::get_field_spans(layout).map(|span| {
+                FieldSpan {
+                    offset: base.checked_add(span.offset).expect("Field offset overflows OffsetType"),
+                    length: span.length,
+                }
+            }));
+        }
+        Box::new(spans.into_iter())
+    }
+
+    pub fn named_field_spans<T>(&self, path: &str, len: usize) -> Box<Iterator<Item = NamedFieldSpan>>
+        where T: LayoutDynamicField<Layout = L>
+    {
+        let mut spans = Vec::new();
+        for i in 0..len {
+            let (layout, base) = self.element(i).expect("Struct array element offset overflows OffsetType");
+            spans.extend(T::get_named_field_spans(layout, &::index_field_path(path, i)).map(|mut named| {
+                named.span.offset = base.checked_add(named.span.offset)
+                    .expect("Field offset overflows OffsetType");
+                named
+            }));
+        }
+        Box::new(spans.into_iter())
+    }
+}
+
+impl<L> StructArrayLayout<L> {
+    pub unsafe fn write_elements<T>(&self, bytes: *mut u8, values: &[T])
+        where T: CopyDynamicField<Layout = L>
+    {
+        for (i, value) in values[..self.len()].iter().enumerate() {
+            let (layout, base) = self.element(i).expect("Struct array element offset overflows OffsetType");
+            T::write_field(layout, bytes.offset(base as isize), value);
+        }
+    }
+
+    pub unsafe fn read_elements<T>(&self, bytes: *const u8, out: &mut [T])
+        where T: CopyDynamicField<Layout = L>
+    {
+        for (i, out) in out[..self.len()].iter_mut().enumerate() {
+            let (layout, base) = self.element(i).expect("Struct array element offset overflows OffsetType");
+            T::read_field(layout, bytes.offset(base as isize), out);
+        }
+    }
+}
+
+/// Accessor for an array of structs that creates the accessors of the elements on demand.
+pub struct StructArrayAccessor<'a, T: LayoutDynamicField + 'a> {
+    layout: &'a StructArrayLayout<T::Layout>,
+    bytes: *mut u8,
+    size: usize,
+    length: usize,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: LayoutDynamicField + 'a> StructArrayAccessor<'a, T> {
+    pub unsafe fn new(layout: &'a StructArrayLayout<T::Layout>,
+                      bytes: *mut u8,
+                      size: usize,
+                      len: usize)
+                      -> StructArrayAccessor<'a, T> {
+        StructArrayAccessor {
+            layout: layout,
+            bytes: bytes,
+            size: size,
+            length: len,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get<'b>(&'b mut self, index: usize) -> Option<<T as AccessDynamicField<'b>>::Accessor>
+        where T: AccessDynamicField<'b>
+    {
+        if index >= self.length {
+            return None;
+        }
+        unsafe { element_accessor::<T>(self.layout, self.bytes, self.size, index) }
+    }
+
+    pub fn index<'b>(&'b mut self, index: usize) -> <T as AccessDynamicField<'b>>::Accessor
+        where T: AccessDynamicField<'b>
+    {
+        let length = self.length;
+        match self.get(index) {
+            Some(accessor) => accessor,
+            None => {
+                panic!("StructArrayAccessor index out of bounds: the len is {} but the index is {}",
+                       length,
+                       index)
+            }
+        }
+    }
+
+    pub fn iter_mut<'b>(&'b mut self) -> StructArrayIterMut<'b, T> {
+        StructArrayIterMut {
+            layout: self.layout,
+            bytes: self.bytes,
+            size: self.size,
+            front: 0,
+            back: self.length,
+            phantom: PhantomData,
+        }
+    }
+}
+
+unsafe fn element_accessor<'b, T>(layout: &'b StructArrayLayout<T::Layout>,
+                                  bytes: *mut u8,
+                                  size: usize,
+                                  index: usize)
+                                  -> Option<<T as AccessDynamicField<'b>>::Accessor>
+    where T: AccessDynamicField<'b>
+{
+    layout.element(index).map(|(layout, base)| {
+        T::accessor_from_layout(layout,
+                                bytes.offset(base as isize),
+                                size.saturating_sub(base as usize))
+    })
+}
+
+pub struct StructArrayIterMut<'b, T: LayoutDynamicField + 'b> {
+    layout: &'b StructArrayLayout<T::Layout>,
+    bytes: *mut u8,
+    size: usize,
+    front: usize,
+    back: usize,
+    phantom: PhantomData<&'b mut T>,
+}
+
+impl<'b, T: AccessDynamicField<'b> + 'b> Iterator for StructArrayIterMut<'b, T> {
+    type Item = <T as AccessDynamicField<'b>>::Accessor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        unsafe { element_accessor::<T>(self.layout, self.bytes, self.size, self.front - 1) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'b, T: AccessDynamicField<'b> + 'b> DoubleEndedIterator for StructArrayIterMut<'b, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        unsafe { element_accessor::<T>(self.layout, self.bytes, self.size, self.back) }
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use vector_types::*;
+use matrix_types::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScalarType {
+    Float,
+    Int,
+    Uint,
+}
+
+/// Shape of a type as seen by a shader, independent of any concrete layout.
+#[derive(Debug, Copy, Clone)]
+pub enum TypeInfo {
+    Scalar(ScalarType),
+    Vector(ScalarType, usize),
+    /// A vector that MSL does not pad, e.g. `packed_float3`. Other rules lay it out like a
+    /// `Vector`.
+    PackedVector(ScalarType, usize),
+    Matrix { columns: usize, rows: usize },
+    Array(&'static TypeInfo, usize),
+    /// An array whose length depends on the size of the bound buffer.
+    RuntimeArray(&'static TypeInfo),
+    Struct(&'static StructInfo),
+}
+
+#[derive(Debug)]
+pub struct StructInfo {
+    pub name: &'static str,
+    pub fields: &'static [FieldInfo],
+}
+
+#[derive(Debug)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub ty: &'static TypeInfo,
+}
+
+pub trait DescribeType {
+    const TYPE_INFO: TypeInfo;
+}
+
+pub trait Scalar {
+    const SCALAR_TYPE: ScalarType;
+}
+
+impl TypeInfo {
+    /// Number of bytes of actual data in the type, not counting any padding a layout may add.
+    pub fn data_size(&self) -> usize {
+        match *self {
+            TypeInfo::Scalar(_) => 4,
+            TypeInfo::Vector(_, count) |
+            TypeInfo::PackedVector(_, count) => 4 * count,
+            TypeInfo::Matrix { columns, rows } => 4 * columns * rows,
+            TypeInfo::Array(element, len) => element.data_size() * len,
+            TypeInfo::RuntimeArray(_) => 0,
+            TypeInfo::Struct(info) => info.fields.iter().map(|f| f.ty.data_size()).sum(),
+        }
+    }
+}
+
+impl<T: DescribeType, const N: usize> DescribeType for [T; N] {
+    const TYPE_INFO: TypeInfo = TypeInfo::Array(&T::TYPE_INFO, N);
+}
+
+macro_rules! impl_scalar {
+    ($scalar:ty, $scalar_type:ident) => (
+        impl Scalar for $scalar {
+            const SCALAR_TYPE: ScalarType = ScalarType::$scalar_type;
+        }
+
+        impl DescribeType for $scalar {
+            const TYPE_INFO: TypeInfo = TypeInfo::Scalar(ScalarType::$scalar_type);
+        }
+    )
+}
+
+impl_scalar!(f32, Float);
+impl_scalar!(i32, Int);
+impl_scalar!(u32, Uint);
+
+macro_rules! impl_vector {
+    ($vector_type:ident : $field_type:ty [$field_count:expr]) => (
+        impl DescribeType for $vector_type {
+            const TYPE_INFO: TypeInfo = TypeInfo::Vector(<$field_type as Scalar>::SCALAR_TYPE, $field_count);
+        }
+    )
+}
+
+impl_vector!(Vec2: f32 [2]);
+impl_vector!(IVec2: i32 [2]);
+impl_vector!(UVec2: u32 [2]);
+
+impl_vector!(Vec3: f32 [3]);
+impl_vector!(IVec3: i32 [3]);
+impl_vector!(UVec3: u32 [3]);
+
+macro_rules! impl_packed_vector {
+    ($vector_type:ident : $field_type:ty [$field_count:expr]) => (
+        impl DescribeType for $vector_type {
+            const TYPE_INFO: TypeInfo = TypeInfo::PackedVector(<$field_type as Scalar>::SCALAR_TYPE, $field_count);
+        }
+    )
+}
+
+impl_packed_vector!(PackedVec3: f32 [3]);
+impl_packed_vector!(PackedIVec3: i32 [3]);
+impl_packed_vector!(PackedUVec3: u32 [3]);
+
+impl_vector!(Vec4: f32 [4]);
+impl_vector!(IVec4: i32 [4]);
+impl_vector!(UVec4: u32 [4]);
+
+macro_rules! impl_matrix {
+    ($matrix_type:ident [$column_count:expr][$row_count:expr]) => (
+        impl DescribeType for $matrix_type {
+            const TYPE_INFO: TypeInfo = TypeInfo::Matrix { columns: $column_count, rows: $row_count };
+        }
+    )
+}
+
+impl_matrix!(Matrix2 [2][2]);
+impl_matrix!(Matrix2x3 [2][3]);
+impl_matrix!(Matrix2x4 [2][4]);
+impl_matrix!(Matrix3x2 [3][2]);
+impl_matrix!(Matrix3 [3][3]);
+impl_matrix!(Matrix3x4 [3][4]);
+impl_matrix!(Matrix4x2 [4][2]);
+impl_matrix!(Matrix4x3 [4][3]);
+impl_matrix!(Matrix4 [4][4]);
+
+/// Type of a block member as reported by the graphics API. Only the types this crate has Rust
+/// counterparts for are included.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GlslType {
+    Scalar(ScalarType),
+    Vector(ScalarType, usize),
+    Matrix { columns: usize, rows: usize },
+}
+
+impl GlslType {
+    /// Converts a type from `glGetActiveUniformsiv(GL_UNIFORM_TYPE)` and similar queries.
+    pub fn from_gl_enum(value: u32) -> Option<GlslType> {
+        use self::GlslType::*;
+        use self::ScalarType::*;
+        let ty = match value {
+            0x1406 => Scalar(Float),
+            0x8B50 => Vector(Float, 2),
+            0x8B51 => Vector(Float, 3),
+            0x8B52 => Vector(Float, 4),
+            0x1404 => Scalar(Int),
+            0x8B53 => Vector(Int, 2),
+            0x8B54 => Vector(Int, 3),
+            0x8B55 => Vector(Int, 4),
+            0x1405 => Scalar(Uint),
+            0x8DC6 => Vector(Uint, 2),
+            0x8DC7 => Vector(Uint, 3),
+            0x8DC8 => Vector(Uint, 4),
+            0x8B5A => Matrix { columns: 2, rows: 2 },
+            0x8B5B => Matrix { columns: 3, rows: 3 },
+            0x8B5C => Matrix { columns: 4, rows: 4 },
+            0x8B65 => Matrix { columns: 2, rows: 3 },
+            0x8B66 => Matrix { columns: 2, rows: 4 },
+            0x8B67 => Matrix { columns: 3, rows: 2 },
+            0x8B68 => Matrix { columns: 3, rows: 4 },
+            0x8B69 => Matrix { columns: 4, rows: 2 },
+            0x8B6A => Matrix { columns: 4, rows: 3 },
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    /// The type of a scalar, vector or matrix `TypeInfo`.
+    pub fn from_type_info(info: &TypeInfo) -> Option<GlslType> {
+        match *info {
+            TypeInfo::Scalar(scalar) => Some(GlslType::Scalar(scalar)),
+            TypeInfo::Vector(scalar, count) |
+            TypeInfo::PackedVector(scalar, count) => Some(GlslType::Vector(scalar, count)),
+            TypeInfo::Matrix { columns, rows } => Some(GlslType::Matrix { columns: columns, rows: rows }),
+            _ => None,
+        }
+    }
+
+    pub fn is_matrix(&self) -> bool {
+        match *self {
+            GlslType::Matrix { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for GlslType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = |scalar| match scalar {
+            ScalarType::Float => "",
+            ScalarType::Int => "i",
+            ScalarType::Uint => "u",
+        };
+        match *self {
+            GlslType::Scalar(ScalarType::Float) => write!(f, "float"),
+            GlslType::Scalar(ScalarType::Int) => write!(f, "int"),
+            GlslType::Scalar(ScalarType::Uint) => write!(f, "uint"),
+            GlslType::Vector(scalar, count) => write!(f, "{}vec{}", prefix(scalar), count),
+            GlslType::Matrix { columns, rows } if columns == rows => write!(f, "mat{}", columns),
+            GlslType::Matrix { columns, rows } => write!(f, "mat{}x{}", columns, rows),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::marker::PhantomData;
+use {LayoutInfo, LoadOptions, LayoutDynamicField, AccessDynamicField, LayoutArrayDynamicField,
+     AccessArrayDynamicField, CopyDynamicField, LayoutError, FieldSpan, NamedFieldSpan, OffsetType, StrideType};
+use type_info::{DescribeType, TypeInfo};
+use static_layout::AccessStaticField;
+use layout_rules::ConstRules;
+
+/// Marker for a runtime-sized array at the end of a storage block, e.g. `Particle particles[];`.
+///
+/// The field takes no space in the Rust struct. Its accessor holds as many elements as fit in
+/// the bytes given to `accessor()`.
+pub struct Unsized<T>(PhantomData<T>);
+
+impl<T> Unsized<T> {
+    pub fn new() -> Unsized<T> {
+        Unsized(PhantomData)
+    }
+}
+
+impl<T> Default for Unsized<T> {
+    fn default() -> Unsized<T> {
+        Unsized::new()
+    }
+}
+
+impl<T> Copy for Unsized<T> {}
+
+impl<T> Clone for Unsized<T> {
+    fn clone(&self) -> Unsized<T> {
+        *self
+    }
+}
+
+impl<T> fmt::Debug for Unsized<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unsized")
+    }
+}
+
+pub struct UnsizedArrayLayout<L> {
+    offset: OffsetType,
+    stride: StrideType,
+    /// Bytes from the start of an element to the end of its last field, at most the stride.
+    element_size: usize,
+    layout: L,
+}
+
+impl<L> UnsizedArrayLayout<L> {
+    /// Number of elements that fit in a buffer of `size` bytes. The last element only needs
+    /// room for its fields, not for the padding up to the stride.
+    pub fn len_for_size(&self, size: usize) -> usize {
+        let offset = self.offset as usize;
+        if self.stride == 0 || size < offset + self.element_size {
+            0
+        } else {
+            (size - offset - self.element_size) / self.stride as usize + 1
+        }
+    }
+}
+
+// Offset and stride of the array types that can be runtime-sized
+fn array_offset_and_stride(layout_field: LayoutInfo) -> Option<(OffsetType, StrideType)> {
+    match layout_field {
+        LayoutInfo::ArrayField(offset, stride) |
+        LayoutInfo::MatrixArrayField(offset, stride, _) |
+        LayoutInfo::StructArrayStrideField(offset, stride, _) |
+        LayoutInfo::ArrayOfArraysField(offset, stride, _) => Some((offset, stride)),
+        _ => None,
+    }
+}
+
+// The layout itself does not know how long the array is, so spans are given for no elements.
+impl<T: LayoutArrayDynamicField> LayoutDynamicField for Unsized<T> {
+    type Layout = UnsizedArrayLayout<T::Layout>;
+
+    fn make_layout(layout_field: LayoutInfo, options: &LoadOptions) -> Result<Self::Layout, LayoutError> {
+        // A declared length on a runtime-sized array is meaningless, the buffer decides it
+        let layout_field = match layout_field {
+            LayoutInfo::ArrayLength(_, inner) => inner.field_layout(),
+            LayoutInfo::Typed(ty, inner) => {
+                match inner.field_layout() {
+                    LayoutInfo::ArrayLength(_, inner) => LayoutInfo::Typed(ty, inner),
+                    _ => layout_field,
+                }
+            }
+            other => other,
+        };
+        let (offset, stride) = array_offset_and_stride(layout_field.strip_type().0)
+            .ok_or(layout_field.unexpected("array"))?;
+        let layout = T::make_layout(layout_field, 0, options)?;
+        let element_end = T::get_field_spans(&layout, 1)
+            .map(|span| span.offset as usize + span.length as usize)
+            .max()
+            .unwrap_or(offset as usize);
+        Ok(UnsizedArrayLayout {
+            offset: offset,
+            stride: stride,
+            element_size: element_end - offset as usize,
+            layout: layout,
+        })
+    }
+
+    fn get_field_spans(layout: &Self::Layout) -> Box<Iterator<Item = FieldSpan>> {
+        T::get_field_spans(&layout.layout, 0)
+    }
+
+    fn get_named_field_spans(layout: &Self::Layout, path: &str) -> Box<Iterator<Item = NamedFieldSpan>> {
+        T::get_named_field_spans(&layout.layout, path, 0)
+    }
+}
+
+impl<'a, T: AccessArrayDynamicField<'a>> AccessDynamicField<'a> for Unsized<T>
+    where T::Layout: 'a
+{
+    type Accessor = T::Accessor;
+
+    unsafe fn accessor_from_layout(layout: &'a Self::Layout,
+                                   bytes: *mut u8,
+                                   size: usize)
+                                   -> Self::Accessor {
+        T::accessor_from_layout(&layout.layout, bytes, size, layout.len_for_size(size))
+    }
+}
+
+// The elements are not part of the Rust value, so there is nothing to copy.
+unsafe impl<T: LayoutArrayDynamicField> CopyDynamicField for Unsized<T> {
+    unsafe fn write_field(_: &Self::Layout, _: *mut u8, _: &Self) {}
+
+    unsafe fn read_field(_: &Self::Layout, _: *const u8, _: &mut Self) {}
+}
+
+impl<T: DescribeType> DescribeType for Unsized<T> {
+    const TYPE_INFO: TypeInfo = TypeInfo::RuntimeArray(&T::TYPE_INFO);
+}
+
+impl<'a, T: DescribeType + 'a> AccessStaticField<'a> for Unsized<T> {
+    type Accessor = UnsizedStaticAccessor<'a, T>;
+
+    unsafe fn static_accessor(bytes: *mut u8, size: usize, rules: ConstRules) -> Self::Accessor {
+        let stride = rules.array_stride(&T::TYPE_INFO);
+        let element_size = rules.size(&T::TYPE_INFO);
+        let length = if stride == 0 || size < element_size {
+            0
+        } else {
+            (size - element_size) / stride + 1
+        };
+        UnsizedStaticAccessor {
+            bytes: bytes,
+            size: size,
+            stride: stride,
+            length: length,
+            rules: rules,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Accessor for a runtime-sized array in a static layout, holding as many elements as fit in
+/// the buffer. The accessors of the elements are created on demand.
+pub struct UnsizedStaticAccessor<'a, T: 'a> {
+    bytes: *mut u8,
+    size: usize,
+    stride: usize,
+    length: usize,
+    rules: ConstRules,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: 'a> UnsizedStaticAccessor<'a, T> {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get<'b>(&'b mut self, index: usize) -> Option<<T as AccessStaticField<'b>>::Accessor>
+        where T: AccessStaticField<'b>
+    {
+        if index >= self.length {
+            return None;
+        }
+        let offset = index * self.stride;
+        unsafe { Some(T::static_accessor(self.bytes.add(offset), self.size - offset, self.rules)) }
+    }
+
+    pub fn index<'b>(&'b mut self, index: usize) -> <T as AccessStaticField<'b>>::Accessor
+        where T: AccessStaticField<'b>
+    {
+        let length = self.length;
+        match self.get(index) {
+            Some(accessor) => accessor,
+            None => {
+                panic!("UnsizedStaticAccessor index out of bounds: the len is {} but the index is {}",
+                       length,
+                       index)
+            }
+        }
+    }
+}
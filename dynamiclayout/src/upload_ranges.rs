@@ -0,0 +1,71 @@
+use {FieldSpan, OffsetType, LengthType};
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CoalesceOptions {
+    /// Spans separated by a gap of at most this many bytes are merged into one range.
+    pub max_gap: LengthType,
+    /// Range starts are rounded down and ends rounded up to a multiple of this. 0 disables it.
+    pub granularity: LengthType,
+    /// End of the buffer; aligned ranges are clipped so they never extend past it.
+    pub limit: Option<OffsetType>,
+}
+
+/// Turns a set of spans (for example from `get_field_spans`) into a minimal, sorted list of
+/// non-overlapping ranges to upload.
+pub fn coalesce_spans<I>(spans: I, options: &CoalesceOptions) -> Vec<FieldSpan>
+    where I: IntoIterator<Item = FieldSpan>
+{
+    let granularity = options.granularity as usize;
+    let limit = options.limit.map(|limit| limit as usize);
+    let mut ranges: Vec<(usize, usize)> = spans.into_iter()
+        .filter(|span| span.length > 0)
+        .map(|span| {
+            let mut start = span.offset as usize;
+            let mut end = start + span.length as usize;
+            if granularity > 1 {
+                start -= start % granularity;
+                end += (granularity - end % granularity) % granularity;
+            }
+            if let Some(limit) = limit {
+                end = ::std::cmp::min(end, ::std::cmp::max(limit, start));
+            }
+            (start, end)
+        })
+        .collect();
+    ranges.sort();
+
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        if let Some(last) = merged.last_mut() {
+            if start <= last.1 + options.max_gap as usize {
+                last.1 = ::std::cmp::max(last.1, end);
+                continue;
+            }
+        }
+        merged.push((start, end));
+    }
+
+    merged.into_iter()
+        .filter(|&(start, end)| end > start)
+        .map(|(start, end)| {
+            FieldSpan {
+                offset: start as OffsetType,
+                length: (end - start) as LengthType,
+            }
+        })
+        .collect()
+}
+
+/// The spans whose bytes differ between `old` and `new`. Spans reaching past the end of `old`
+/// always count as changed, so an empty `old` returns all spans.
+pub fn changed_spans<I>(spans: I, old: &[u8], new: &[u8]) -> Vec<FieldSpan>
+    where I: IntoIterator<Item = FieldSpan>
+{
+    spans.into_iter()
+        .filter(|span| {
+            let start = span.offset as usize;
+            let end = start + span.length as usize;
+            end > old.len() || old[start..end] != new[start..end]
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use {FieldSpan, NamedFieldSpan, LayoutDynamicField, OffsetType, LengthType, StrideType};
+use upload_ranges::{coalesce_spans, CoalesceOptions};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutIssue {
+    /// Two fields share at least one byte.
+    Overlap {
+        first: String,
+        second: String,
+    },
+    /// A field offset is not a multiple of the alignment of its type.
+    Misaligned {
+        path: String,
+        offset: OffsetType,
+        alignment: LengthType,
+    },
+    /// Consecutive array elements are closer to each other than the size of an element.
+    StrideTooSmall {
+        path: String,
+        stride: StrideType,
+        element_size: LengthType,
+    },
+}
+
+#[derive(Debug)]
+pub struct ValidationReport {
+    pub issues: Vec<LayoutIssue>,
+    /// Byte ranges before `size` that are not covered by any field.
+    pub padding: Vec<FieldSpan>,
+    /// End of the last field.
+    pub size: OffsetType,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn padding_bytes(&self) -> usize {
+        self.padding.iter().map(|span| span.length as usize).sum()
+    }
+}
+
+pub fn validate<T: LayoutDynamicField>(layout: &T::Layout) -> ValidationReport {
+    let spans: Vec<_> = T::get_named_field_spans(layout, "").collect();
+    validate_spans(&spans)
+}
+
+pub fn validate_spans(spans: &[NamedFieldSpan]) -> ValidationReport {
+    let mut issues = Vec::new();
+
+    for span in spans {
+        if span.alignment > 1 && span.span.offset as usize % span.alignment as usize != 0 {
+            issues.push(LayoutIssue::Misaligned {
+                path: span.path.clone(),
+                offset: span.span.offset,
+                alignment: span.alignment,
+            });
+        }
+    }
+
+    let mut sorted: Vec<&NamedFieldSpan> = spans.iter().filter(|s| s.span.length > 0).collect();
+    sorted.sort_by_key(|s| s.span.offset);
+    for (i, first) in sorted.iter().enumerate() {
+        for second in sorted[i + 1..].iter().take_while(|s| s.span.offset < end(&first.span)) {
+            issues.push(LayoutIssue::Overlap {
+                first: first.path.clone(),
+                second: second.path.clone(),
+            });
+        }
+    }
+
+    issues.extend(check_strides(spans));
+
+    let covered = coalesce_spans(spans.iter().map(|s| s.span), &CoalesceOptions::default());
+    let mut padding = Vec::new();
+    let mut position = 0;
+    for range in &covered {
+        if range.offset > position {
+            padding.push(FieldSpan {
+                offset: position,
+                length: (range.offset - position) as LengthType,
+            });
+        }
+        position = end(range);
+    }
+
+    ValidationReport {
+        issues: issues,
+        padding: padding,
+        size: position,
+    }
+}
+
+fn end(span: &FieldSpan) -> OffsetType {
+    span.offset.saturating_add(span.length)
+}
+
+// Elements 0 and 1 of every array, keyed by the path of the array. Each leaf is stored along
+// with the remainder of its path so that matching leaves of the two elements can be paired up.
+struct ArrayElements<'a> {
+    first: Vec<(&'a str, &'a NamedFieldSpan)>,
+    second: Vec<(&'a str, &'a NamedFieldSpan)>,
+}
+
+fn check_strides(spans: &[NamedFieldSpan]) -> Vec<LayoutIssue> {
+    let mut arrays: BTreeMap<&str, ArrayElements> = BTreeMap::new();
+    for span in spans {
+        for (array_path, index, rest) in array_indices(&span.path) {
+            if index > 1 {
+                continue;
+            }
+            let elements = arrays.entry(array_path).or_insert_with(|| {
+                ArrayElements {
+                    first: Vec::new(),
+                    second: Vec::new(),
+                }
+            });
+            if index == 0 {
+                elements.first.push((rest, span));
+            } else {
+                elements.second.push((rest, span));
+            }
+        }
+    }
+
+    let mut issues = Vec::new();
+    for (array_path, elements) in arrays {
+        let first = match elements.first.iter().min_by_key(|&&(_, s)| s.span.offset) {
+            Some(&(rest, span)) => (rest, span),
+            None => continue,
+        };
+        let second = match elements.second.iter().find(|&&(rest, _)| rest == first.0) {
+            Some(&(_, span)) => span,
+            None => continue,
+        };
+        if second.span.offset < first.1.span.offset {
+            continue;
+        }
+        let start = first.1.span.offset;
+        let element_end = elements.first.iter().map(|&(_, s)| end(&s.span)).max().unwrap_or(start);
+        let stride = second.span.offset - start;
+        let element_size = element_end - start;
+        let overlapping = elements.first.iter().any(|&(_, a)| {
+            elements.second.iter().any(|&(_, b)| {
+                a.span.offset < end(&b.span) && b.span.offset < end(&a.span)
+            })
+        });
+        if stride < element_size && overlapping {
+            issues.push(LayoutIssue::StrideTooSmall {
+                path: array_path.to_string(),
+                stride: stride as StrideType,
+                element_size: element_size as LengthType,
+            });
+        }
+    }
+    issues
+}
+
+// Every `[index]` in a path as (path of the array, index, rest of the path after the index).
+fn array_indices(path: &str) -> Vec<(&str, usize, &str)> {
+    let mut indices = Vec::new();
+    let mut search_from = 0;
+    while let Some(open) = path[search_from..].find('[').map(|i| i + search_from) {
+        let close = match path[open..].find(']') {
+            Some(i) => i + open,
+            None => break,
+        };
+        if let Ok(index) = path[open + 1..close].parse() {
+            indices.push((&path[..open], index, &path[close + 1..]));
+        }
+        search_from = close + 1;
+    }
+    indices
+}
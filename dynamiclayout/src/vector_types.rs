@@ -47,6 +47,12 @@ make_vector_type!(Vec3: f32 [3] x, y, z);
 make_vector_type!(IVec3: i32 [3] x, y, z);
 make_vector_type!(UVec3: u32 [3] x, y, z);
 
+// Metal's `packed_float3` and friends. The Rust values are the same as the unpacked ones, only
+// the layout rules treat them differently.
+make_vector_type!(PackedVec3: f32 [3] x, y, z);
+make_vector_type!(PackedIVec3: i32 [3] x, y, z);
+make_vector_type!(PackedUVec3: u32 [3] x, y, z);
+
 make_vector_type!(Vec4: f32 [4] x, y, z, w);
 make_vector_type!(IVec4: i32 [4] x, y, z, w);
 make_vector_type!(UVec4: u32 [4] x, y, z, w);
@@ -0,0 +1,467 @@
+use {LayoutInfo, LayoutError, LayoutErrorKind, OffsetType, StrideType, ParseError};
+use computed_layout::{ComputedInfo, ComputedLayout};
+use layout_rules::{LayoutRules, Wgsl, round_up};
+use type_info::{ScalarType, TypeInfo};
+
+/// Type of a member of a WGSL struct.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WgslType {
+    Scalar(ScalarType),
+    Vector(ScalarType, usize),
+    Matrix { columns: usize, rows: usize },
+    /// Element type and length, `None` for a runtime-sized array.
+    Array(Box<WgslType>, Option<usize>),
+    Struct(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WgslMember {
+    pub name: String,
+    pub ty: WgslType,
+    /// Value of an `@align` attribute.
+    pub align: Option<usize>,
+    /// Value of a `@size` attribute.
+    pub size: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WgslStruct {
+    pub name: String,
+    pub members: Vec<WgslMember>,
+}
+
+/// Parses the struct declarations in `source`, skipping everything else.
+pub fn parse_structs(source: &str) -> Result<Vec<WgslStruct>, ParseError> {
+    let mut parser = Parser::new(source)?;
+    let mut structs = Vec::new();
+    while let Some(token) = parser.next() {
+        if token.text == "struct" {
+            structs.push(parser.struct_body()?);
+        }
+    }
+    Ok(structs)
+}
+
+/// Layout of the struct `name` following the WGSL rules and the `@align` and `@size` attributes
+/// of its members, for `DynamicLayout::load_layout`.
+///
+/// Fails for structs that contain themselves and for invalid attributes: `@align` has to be a
+/// power of two no smaller than the alignment of the type, and `@size` no smaller than its size.
+pub fn struct_layout(structs: &[WgslStruct], name: &str) -> Result<ComputedLayout, LayoutError> {
+    let mut calculator = Calculator {
+        structs: structs,
+        visiting: Vec::new(),
+    };
+    let declaration = structs.iter()
+        .find(|s| s.name == name)
+        .ok_or(LayoutError::new(LayoutErrorKind::MissingBlock(name.to_string())))?;
+    calculator.struct_layout(declaration, 0)
+}
+
+struct Calculator<'a> {
+    structs: &'a [WgslStruct],
+    /// Structs whose offsets are being computed, innermost last.
+    visiting: Vec<&'a str>,
+}
+
+impl<'a> Calculator<'a> {
+    fn find(&self, name: &str) -> Result<&'a WgslStruct, LayoutError> {
+        self.structs
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or(LayoutErrorKind::InvalidMember(format!("unknown struct {}", name)).into())
+    }
+
+    fn alignment(&mut self, ty: &WgslType) -> Result<usize, LayoutError> {
+        match *ty {
+            WgslType::Array(ref element, _) => self.alignment(element),
+            WgslType::Struct(ref name) => Ok(self.struct_offsets(self.find(name)?)?.2),
+            ref leaf => Ok(Wgsl.alignment(&leaf_type_info(leaf))),
+        }
+    }
+
+    fn size(&mut self, ty: &WgslType) -> Result<usize, LayoutError> {
+        match *ty {
+            WgslType::Array(ref element, length) => {
+                Ok(self.array_stride(element)? * length.unwrap_or(0))
+            }
+            WgslType::Struct(ref name) => Ok(self.struct_offsets(self.find(name)?)?.1),
+            ref leaf => Ok(Wgsl.size(&leaf_type_info(leaf))),
+        }
+    }
+
+    fn array_stride(&mut self, element: &WgslType) -> Result<usize, LayoutError> {
+        Ok(round_up(self.size(element)?, self.alignment(element)?))
+    }
+
+    // Offsets of the members, size and alignment of the struct
+    fn struct_offsets(&mut self,
+                      declaration: &'a WgslStruct)
+                      -> Result<(Vec<usize>, usize, usize), LayoutError> {
+        if self.visiting.contains(&&declaration.name[..]) {
+            let reason = format!("struct {} contains itself", declaration.name);
+            return Err(LayoutErrorKind::InvalidMember(reason).into());
+        }
+        self.visiting.push(&declaration.name);
+        let offsets = self.member_offsets(declaration);
+        self.visiting.pop();
+        offsets
+    }
+
+    fn member_offsets(&mut self,
+                      declaration: &'a WgslStruct)
+                      -> Result<(Vec<usize>, usize, usize), LayoutError> {
+        let mut offsets = Vec::with_capacity(declaration.members.len());
+        let (mut end, mut struct_alignment) = (0, 1);
+        for member in &declaration.members {
+            let in_member = |e: LayoutError| e.in_field(&member.name);
+            let invalid = |reason: &str| in_member(LayoutErrorKind::InvalidMember(reason.to_string()).into());
+            let natural_alignment = self.alignment(&member.ty).map_err(&in_member)?;
+            let alignment = member.align.unwrap_or(natural_alignment);
+            if !alignment.is_power_of_two() {
+                return Err(invalid("@align is not a power of two"));
+            }
+            if alignment < natural_alignment {
+                return Err(invalid("@align is smaller than the alignment of the type"));
+            }
+            let natural_size = self.size(&member.ty).map_err(&in_member)?;
+            let size = member.size.unwrap_or(natural_size);
+            if size < natural_size {
+                return Err(invalid("@size is smaller than the type"));
+            }
+            let offset = round_up(end, alignment);
+            offsets.push(offset);
+            end = offset + size;
+            struct_alignment = ::std::cmp::max(struct_alignment, alignment);
+        }
+        Ok((offsets, round_up(end, struct_alignment), struct_alignment))
+    }
+
+    fn struct_layout(&mut self,
+                     declaration: &'a WgslStruct,
+                     offset: usize)
+                     -> Result<ComputedLayout, LayoutError> {
+        let (offsets, _, _) = self.struct_offsets(declaration)?;
+        let mut fields = Vec::with_capacity(offsets.len());
+        for (member, member_offset) in declaration.members.iter().zip(offsets) {
+            let computed = self.field(&member.ty, offset + member_offset)
+                .map_err(|e| e.in_field(&member.name))?;
+            fields.push((member.name.clone(), computed));
+        }
+        Ok(ComputedLayout::new(fields))
+    }
+
+    fn field(&mut self, ty: &WgslType, offset: usize) -> Result<ComputedInfo, LayoutError> {
+        if offset > OffsetType::max_value() as usize {
+            return Err(LayoutErrorKind::OffsetOverflow.into());
+        }
+        let (start, offset) = (offset, offset as OffsetType);
+        Ok(match *ty {
+            WgslType::Scalar(_) |
+            WgslType::Vector(..) => ComputedInfo::Info(LayoutInfo::PrimitiveField(offset)),
+            WgslType::Matrix { .. } => {
+                let matrix_stride = Wgsl.matrix_stride(&leaf_type_info(ty)) as StrideType;
+                ComputedInfo::Info(LayoutInfo::ArrayField(offset, matrix_stride))
+            }
+            WgslType::Array(ref element, _) => {
+                let stride = self.array_stride(element)? as StrideType;
+                match **element {
+                    WgslType::Struct(ref name) => {
+                        let element_layout = self.struct_layout(self.find(name)?, 0)?;
+                        ComputedInfo::StructArray(offset, stride, element_layout)
+                    }
+                    WgslType::Array(..) => {
+                        match self.field(element, 0)? {
+                            ComputedInfo::Info(inner) => {
+                                ComputedInfo::ArrayOfArrays(offset, stride, inner)
+                            }
+                            _ => return Err(LayoutErrorKind::UnsupportedType.into()),
+                        }
+                    }
+                    WgslType::Matrix { .. } => {
+                        let matrix_stride = Wgsl.matrix_stride(&leaf_type_info(element));
+                        let info = LayoutInfo::MatrixArrayField(offset,
+                                                                stride,
+                                                                matrix_stride as StrideType);
+                        ComputedInfo::Info(info)
+                    }
+                    _ => ComputedInfo::Info(LayoutInfo::ArrayField(offset, stride)),
+                }
+            }
+            WgslType::Struct(ref name) => {
+                ComputedInfo::Struct(self.struct_layout(self.find(name)?, start)?)
+            }
+        })
+    }
+}
+
+fn leaf_type_info(ty: &WgslType) -> TypeInfo {
+    match *ty {
+        WgslType::Scalar(scalar) => TypeInfo::Scalar(scalar),
+        WgslType::Vector(scalar, count) => TypeInfo::Vector(scalar, count),
+        WgslType::Matrix { columns, rows } => {
+            TypeInfo::Matrix {
+                columns: columns,
+                rows: rows,
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn scalar_type(name: &str) -> Option<ScalarType> {
+    match name {
+        "f32" => Some(ScalarType::Float),
+        "i32" => Some(ScalarType::Int),
+        "u32" => Some(ScalarType::Uint),
+        _ => None,
+    }
+}
+
+// Suffix of shorthands like `vec3f`
+fn suffix_scalar_type(suffix: &str) -> Option<ScalarType> {
+    match suffix {
+        "f" => Some(ScalarType::Float),
+        "i" => Some(ScalarType::Int),
+        "u" => Some(ScalarType::Uint),
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Result<Parser<'a>, ParseError> {
+        let mut tokens = Vec::new();
+        // Block comments nest in WGSL
+        let mut comment_depth = 0;
+        for (i, line) in source.lines().enumerate() {
+            let mut rest = line;
+            while !rest.is_empty() {
+                if rest.starts_with("/*") {
+                    comment_depth += 1;
+                    rest = &rest[2..];
+                    continue;
+                }
+                if comment_depth > 0 {
+                    if rest.starts_with("*/") {
+                        comment_depth -= 1;
+                        rest = &rest[2..];
+                    } else {
+                        let c = rest.chars().next().unwrap();
+                        rest = &rest[c.len_utf8()..];
+                    }
+                    continue;
+                }
+                if rest.starts_with("//") {
+                    break;
+                }
+                let c = rest.chars().next().unwrap();
+                let len = if c.is_alphanumeric() || c == '_' {
+                    rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len())
+                } else {
+                    c.len_utf8()
+                };
+                if !c.is_whitespace() {
+                    tokens.push(Token {
+                        text: &rest[..len],
+                        line: i + 1,
+                    });
+                }
+                rest = &rest[len..];
+            }
+        }
+        if comment_depth > 0 {
+            return Err(ParseError {
+                line: source.lines().count(),
+                message: "unterminated comment".to_string(),
+            });
+        }
+        Ok(Parser {
+            tokens: tokens,
+            position: 0,
+        })
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|token| token.text)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        let line = self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map(|token| token.line)
+            .unwrap_or(0);
+        ParseError {
+            line: line,
+            message: message.to_string(),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), ParseError> {
+        if self.peek() != Some(text) {
+            return Err(self.error(&format!("expected `{}`", text)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<&'a str, ParseError> {
+        match self.peek() {
+            Some(text) if text.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                self.position += 1;
+                Ok(text)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    // Integer literals may have an `i` or `u` suffix
+    fn number(&mut self) -> Result<usize, ParseError> {
+        let number = self.peek()
+            .map(|text| text.trim_end_matches(|c| c == 'i' || c == 'u'))
+            .and_then(|text| text.parse().ok())
+            .ok_or(self.error("expected a number"))?;
+        self.position += 1;
+        Ok(number)
+    }
+
+    // Name and members of a struct, after the `struct` keyword
+    fn struct_body(&mut self) -> Result<WgslStruct, ParseError> {
+        let name = self.identifier()?;
+        self.expect("{")?;
+        let mut members = Vec::new();
+        while self.peek() != Some("}") {
+            let (mut align, mut size) = (None, None);
+            while self.peek() == Some("@") {
+                self.position += 1;
+                let attribute = self.identifier()?;
+                match attribute {
+                    "align" | "size" => {
+                        self.expect("(")?;
+                        let value = Some(self.number()?);
+                        self.expect(")")?;
+                        if attribute == "align" {
+                            align = value;
+                        } else {
+                            size = value;
+                        }
+                    }
+                    _ => {
+                        if self.peek() == Some("(") {
+                            while self.next().map(|token| token.text) != Some(")") {
+                                if self.peek().is_none() {
+                                    return Err(self.error("expected `)`"));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            let member_name = self.identifier()?;
+            self.expect(":")?;
+            let ty = self.member_type()?;
+            members.push(WgslMember {
+                name: member_name.to_string(),
+                ty: ty,
+                align: align,
+                size: size,
+            });
+            if self.peek() != Some(",") {
+                break;
+            }
+            self.position += 1;
+        }
+        self.expect("}")?;
+        if self.peek() == Some(";") {
+            self.position += 1;
+        }
+        Ok(WgslStruct {
+            name: name.to_string(),
+            members: members,
+        })
+    }
+
+    fn member_type(&mut self) -> Result<WgslType, ParseError> {
+        let name = self.identifier()?;
+        if let Some(scalar) = scalar_type(name) {
+            return Ok(WgslType::Scalar(scalar));
+        }
+        // `vec3<f32>` or the `vec3f` shorthand, same for matrices
+        let (base, suffix) = match name.find(|c: char| c.is_numeric()) {
+            Some(start) => {
+                let end = name[start..]
+                    .rfind(|c: char| c.is_numeric())
+                    .map(|i| start + i + 1)
+                    .unwrap();
+                (&name[..end], &name[end..])
+            }
+            None => (name, ""),
+        };
+        let digits: Vec<usize> = base.chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|d| d as usize)
+            .collect();
+        let is_vector = base.starts_with("vec") && digits.len() == 1;
+        let is_matrix = base.starts_with("mat") && digits.len() == 2;
+        if is_vector || is_matrix {
+            let scalar = if suffix.is_empty() {
+                self.expect("<")?;
+                let scalar = self.identifier()?;
+                self.expect(">")?;
+                scalar_type(scalar)
+            } else {
+                suffix_scalar_type(suffix)
+            };
+            return match (scalar, is_vector) {
+                (Some(scalar), true) => Ok(WgslType::Vector(scalar, digits[0])),
+                (Some(ScalarType::Float), false) => {
+                    Ok(WgslType::Matrix {
+                        columns: digits[0],
+                        rows: digits[1],
+                    })
+                }
+                _ => Err(self.error(&format!("unsupported type {}", name))),
+            };
+        }
+        match name {
+            "array" => {
+                self.expect("<")?;
+                let element = self.member_type()?;
+                let length = if self.peek() == Some(",") {
+                    self.position += 1;
+                    Some(self.number()?)
+                } else {
+                    None
+                };
+                self.expect(">")?;
+                Ok(WgslType::Array(Box::new(element), length))
+            }
+            // Atomics are stored like the integer they wrap
+            "atomic" => {
+                self.expect("<")?;
+                let ty = self.member_type()?;
+                self.expect(">")?;
+                Ok(ty)
+            }
+            "bool" | "f16" => Err(self.error(&format!("unsupported type {}", name))),
+            _ => Ok(WgslType::Struct(name.to_string())),
+        }
+    }
+}
@@ -4,9 +4,25 @@ extern crate dynamiclayout;
 #[macro_use]
 extern crate dynamiclayout_derive;
 
-use dynamiclayout::vector_types::{Vec2, Vec3, Vec4};
-use dynamiclayout::matrix_types::{Matrix4, Matrix2x3};
-use dynamiclayout::{DynamicLayout, LayoutDynamicField, LayoutInfo};
+use dynamiclayout::vector_types::{Vec2, Vec3, Vec4, PackedVec3};
+use dynamiclayout::matrix_types::{Matrix2, Matrix4, Matrix2x3, Matrix4x3};
+use dynamiclayout::unsized_array::Unsized;
+use dynamiclayout::dynamic_buffer::DynamicBuffer;
+use dynamiclayout::diff::diff;
+use dynamiclayout::block_arena::{BlockArena, ArenaMode, ArenaSlot};
+use dynamiclayout::introspection::{block_layout, BlockMember, MockProgram, ProgramIntrospection};
+use dynamiclayout::type_info::{DescribeType, GlslType, ScalarType};
+use dynamiclayout::field_matching::{match_fields, FieldReport};
+use dynamiclayout::glsl::{block_declaration, parse_declarations, rust_definitions, BlockKind, Packing};
+use dynamiclayout::{DynamicLayout, LayoutDynamicField, LayoutInfo, FieldSpan, LengthType,
+                    LoadOptions, ArrayLengthPolicy, FieldMatching, LayoutError, LayoutErrorKind, layout_size};
+use dynamiclayout::upload_ranges::{coalesce_spans, CoalesceOptions};
+use dynamiclayout::validation::{validate, LayoutIssue};
+use dynamiclayout::layout_rules::{LayoutRules, ConstRules, Std140, Std430, HlslCbuffer, Wgsl, Msl};
+use dynamiclayout::wgsl::{parse_structs, struct_layout, WgslType};
+use dynamiclayout::computed_layout::compute_layout;
+use dynamiclayout::reflection::{Reflect, FieldKind, FieldValue, VisitFields};
+use dynamiclayout::padding_report::padding_report;
 use dynamiclayout::LayoutInfo::*;
 
 #[repr(C, packed)]
@@ -145,25 +161,25 @@ fn one_to_one_mapping() {
 
     let acc = layout.accessor(&mut bytes);
 
-    assert_eq!(foo.three.x, acc.three.x);
-    assert_eq!(foo.three.y, acc.three.y);
-    assert_eq!(foo.three.z, acc.three.z);
+    assert_eq!({ foo.three.x }, { acc.three.x });
+    assert_eq!({ foo.three.y }, { acc.three.y });
+    assert_eq!({ foo.three.z }, { acc.three.z });
 
-    assert_eq!(foo.one, *acc.one);
+    assert_eq!({ foo.one }, *acc.one);
 
-    assert_eq!(foo.four.x, acc.four.x);
-    assert_eq!(foo.four.y, acc.four.y);
-    assert_eq!(foo.four.z, acc.four.z);
-    assert_eq!(foo.four.w, acc.four.w);
+    assert_eq!({ foo.four.x }, { acc.four.x });
+    assert_eq!({ foo.four.y }, { acc.four.y });
+    assert_eq!({ foo.four.z }, { acc.four.z });
+    assert_eq!({ foo.four.w }, { acc.four.w });
 
-    assert_eq!(foo.two.x, acc.two.x);
-    assert_eq!(foo.two.y, acc.two.y);
+    assert_eq!({ foo.two.x }, { acc.two.x });
+    assert_eq!({ foo.two.y }, { acc.two.y });
 
-    assert_eq!(foo.compound.one, *acc.compound.one);
-    assert_eq!(foo.compound.four.x, acc.compound.four.x);
-    assert_eq!(foo.compound.four.y, acc.compound.four.y);
-    assert_eq!(foo.compound.four.z, acc.compound.four.z);
-    assert_eq!(foo.compound.four.w, acc.compound.four.w);
+    assert_eq!({ foo.compound.one }, *acc.compound.one);
+    assert_eq!({ foo.compound.four.x }, { acc.compound.four.x });
+    assert_eq!({ foo.compound.four.y }, { acc.compound.four.y });
+    assert_eq!({ foo.compound.four.z }, { acc.compound.four.z });
+    assert_eq!({ foo.compound.four.w }, { acc.compound.four.w });
 
     assert_eq!(foo.compound.matrix[0][0], acc.compound.matrix[0][0]);
     assert_eq!(foo.compound.matrix[0][1], acc.compound.matrix[0][1]);
@@ -183,11 +199,11 @@ fn one_to_one_mapping() {
     assert_eq!(foo.compound.matrix[3][3], acc.compound.matrix[3][3]);
 
     acc.three.y = 999.0;
-    assert_eq!(foo.three.y, 999.0);
+    assert_eq!({ foo.three.y }, 999.0);
     acc.two[0] = 888.0;
-    assert_eq!(foo.two.x, 888.0);
+    assert_eq!({ foo.two.x }, 888.0);
     *acc.one = 777.0;
-    assert_eq!(foo.one, 777.0);
+    assert_eq!({ foo.one }, 777.0);
 }
 
 #[test]
@@ -246,6 +262,219 @@ fn field_spans() {
     assert_eq!((max.offset + max.length) as usize, FOO_SIZE);
 }
 
+#[test]
+fn named_field_spans() {
+    let layout = make_foo_layout();
+    let spans: Vec<_> = <Foo as LayoutDynamicField>::get_named_field_spans(&layout, "").collect();
+    let paths: Vec<_> = spans.iter().map(|s| s.path.as_str()).collect();
+    assert_eq!(paths,
+               vec!["three", "one", "four", "two", "compound.one", "compound.four",
+                    "compound.matrix[0]", "compound.matrix[1]", "compound.matrix[2]",
+                    "compound.matrix[3]"]);
+    let column = spans.iter().find(|s| s.path == "compound.matrix[2]").unwrap();
+    assert_eq!(column.type_name, "Matrix4");
+    assert_eq!(column.span.offset, 92);
+    assert_eq!(column.span.length, 16);
+
+    let layout = make_struct_array_layout();
+    let spans: Vec<_> = <StructArray as LayoutDynamicField>::get_named_field_spans(&layout, "")
+        .collect();
+    let four = spans.iter().find(|s| s.path == "array[1].four").unwrap();
+    assert_eq!(four.type_name, "Vec4");
+    assert_eq!(four.span.offset, 24);
+    assert_eq!(four.span.length, 16);
+}
+
+#[test]
+fn matrix_array_field_spans() {
+    let layout = make_matrix_array_layout();
+    let spans: Vec<_> = <MatrixArray as LayoutDynamicField>::get_field_spans(&layout).collect();
+    let named: Vec<_> = <MatrixArray as LayoutDynamicField>::get_named_field_spans(&layout, "")
+        .collect();
+    assert_eq!(spans.len(), 4);
+    assert_eq!(named.len(), 4);
+    for (span, named) in spans.iter().zip(named.iter()) {
+        assert_eq!(span.offset, named.span.offset);
+        assert_eq!(span.length, 12);
+    }
+    assert_eq!(named[1].path.as_str(), "array[0][1]");
+    assert_eq!(named[1].span.offset, 24);
+    assert_eq!(named[2].path.as_str(), "array[1][0]");
+    assert_eq!(named[2].span.offset, 12);
+}
+
+#[test]
+fn coalesce_field_spans() {
+    let layout = make_foo_layout();
+    let spans = <Foo as LayoutDynamicField>::get_field_spans(&layout);
+    let ranges = coalesce_spans(spans, &CoalesceOptions::default());
+    assert_eq!(ranges, vec![FieldSpan { offset: 0, length: FOO_SIZE as LengthType }]);
+}
+
+#[test]
+fn coalesce_gaps_and_granularity() {
+    let spans = vec![FieldSpan { offset: 40, length: 4 },
+                     FieldSpan { offset: 4, length: 4 },
+                     FieldSpan { offset: 0, length: 8 },
+                     FieldSpan { offset: 12, length: 4 },
+                     FieldSpan { offset: 100, length: 0 }];
+
+    let exact = coalesce_spans(spans.clone(), &CoalesceOptions::default());
+    assert_eq!(exact,
+               vec![FieldSpan { offset: 0, length: 8 },
+                    FieldSpan { offset: 12, length: 4 },
+                    FieldSpan { offset: 40, length: 4 }]);
+
+    let bridged = coalesce_spans(spans.clone(),
+                                 &CoalesceOptions { max_gap: 4, ..Default::default() });
+    assert_eq!(bridged,
+               vec![FieldSpan { offset: 0, length: 16 }, FieldSpan { offset: 40, length: 4 }]);
+
+    let aligned = coalesce_spans(spans,
+                                 &CoalesceOptions {
+                                     granularity: 32,
+                                     limit: Some(42),
+                                     ..Default::default()
+                                 });
+    assert_eq!(aligned, vec![FieldSpan { offset: 0, length: 42 }]);
+}
+
+#[test]
+fn validate_valid_layout() {
+    let report = validate::<Foo>(&make_foo_layout());
+    assert!(report.is_valid());
+    assert!(report.padding.is_empty());
+    assert_eq!(report.size as usize, FOO_SIZE);
+
+    // Interleaved matrices are fine as long as nothing overlaps
+    let report = validate::<MatrixArray>(&make_matrix_array_layout());
+    assert!(report.is_valid());
+}
+
+#[test]
+fn validate_broken_layout() {
+    const FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("first", PrimitiveField(0)),
+                                                                      ("array", ArrayField(4, 2)),
+                                                                      ("last", PrimitiveField(37))];
+    let layout = PrimitiveArray::load_layout(&FIELDS).unwrap();
+    let report = validate::<PrimitiveArray>(&layout);
+
+    assert!(!report.is_valid());
+    assert!(report.issues.contains(&LayoutIssue::Misaligned {
+        path: "last".to_string(),
+        offset: 37,
+        alignment: 4,
+    }));
+    assert!(report.issues.contains(&LayoutIssue::Overlap {
+        first: "array[0]".to_string(),
+        second: "array[1]".to_string(),
+    }));
+    assert!(report.issues.contains(&LayoutIssue::StrideTooSmall {
+        path: "array".to_string(),
+        stride: 2,
+        element_size: 4,
+    }));
+    assert_eq!(report.padding_bytes(), 15);
+    assert_eq!(report.size, 41);
+    assert_eq!(report.padding, vec![FieldSpan { offset: 22, length: 15 }]);
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Padded {
+    pub a: f32,
+    pub b: Vec3,
+    pub c: f32,
+    pub d: Vec2,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct SampleWeights {
+    pub w0: f32,
+    pub w1: f32,
+    pub w2: f32,
+    pub w3: f32,
+    pub w4: f32,
+    pub w5: f32,
+    pub w6: f32,
+    pub w7: f32,
+    pub w8: f32,
+    pub samples: Unsized<Vec4>,
+}
+
+#[test]
+fn std140_padding_report() {
+    let report = padding_report::<Padded, _>(&Std140).unwrap();
+    let offsets: Vec<_> = report.fields.iter().map(|f| f.offset).collect();
+    assert_eq!(offsets, vec![0, 16, 28, 32]);
+    assert_eq!(report.fields[1].padding_before, 12);
+    assert_eq!(report.size, 48);
+    assert_eq!(report.trailing_padding, 8);
+    assert_eq!(report.wasted(), 20);
+    assert_eq!(report.suggested_size, 32);
+    let mut names = report.suggested_order.clone();
+    names.sort();
+    assert_eq!(names, vec!["a", "b", "c", "d"]);
+
+    let report = padding_report::<PrimitiveArray, _>(&Std140).unwrap();
+    assert_eq!(report.fields[1].offset, 16);
+    assert_eq!(report.fields[1].padding_before, 12);
+    assert_eq!(report.fields[1].internal_padding, 96);
+    assert_eq!(report.fields[2].offset, 144);
+    assert_eq!(report.size, 160);
+
+    let report = padding_report::<Foo, _>(&Std140).unwrap();
+    assert_eq!(report.fields[4].offset, 48);
+    assert_eq!(report.fields[4].internal_padding, 12);
+    assert_eq!(report.size, 144);
+    assert_eq!(report.suggested_size, 144);
+
+    // A runtime-sized array stays last in the suggested order
+    let report = padding_report::<SampleWeights, _>(&Std430).unwrap();
+    assert_eq!(report.suggested_size, 48);
+    assert_eq!(report.suggested_order.last(), Some(&"samples"));
+
+    assert_eq!(padding_report::<f32, _>(&Std140).unwrap_err().kind, LayoutErrorKind::UnsupportedType);
+}
+
+#[test]
+fn large_offsets() {
+    const FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("first", PrimitiveField(0)),
+                                                                      ("array", ArrayField(70000, 16)),
+                                                                      ("last", PrimitiveField(80000))];
+    let layout = PrimitiveArray::load_layout(&FIELDS).unwrap();
+    let spans: Vec<_> = <PrimitiveArray as LayoutDynamicField>::get_field_spans(&layout).collect();
+    assert_eq!(spans[8].offset, 70000 + 7 * 16);
+    assert_eq!(spans[9].offset, 80000);
+
+    let mut bytes = vec![0u8; 80004];
+    {
+        let mut acc = layout.accessor(&mut bytes);
+        acc.array[7] = 42;
+        *acc.last = 43;
+    }
+    assert_eq!(bytes[70000 + 7 * 16], 42);
+    assert_eq!(bytes[80000], 43);
+}
+
+#[test]
+fn overflowing_offsets() {
+    const ARRAY: &'static [(&'static str, LayoutInfo<'static>)] = &[("first", PrimitiveField(0)),
+                                                                     ("array", ArrayField(0xFFFF_FF00, 64)),
+                                                                     ("last", PrimitiveField(0))];
+    assert!(PrimitiveArray::load_layout(&ARRAY).is_err());
+
+    const LAST: &'static [(&'static str, LayoutInfo<'static>)] = &[("first", PrimitiveField(0)),
+                                                                    ("array", ArrayField(4, 4)),
+                                                                    ("last", PrimitiveField(0xFFFF_FFFE))];
+    assert!(PrimitiveArray::load_layout(&LAST).is_err());
+
+    const MATRICES: &'static [(&'static str, LayoutInfo<'static>)] = &[("array",
+                                                                        MatrixArrayField(0xFFFF_FF00, 256, 16))];
+    assert!(MatrixArray::load_layout(&MATRICES).is_err());
+}
+
 #[test]
 fn primitive_array() {
     let layout = make_primitive_array_layout();
@@ -272,6 +501,59 @@ fn primitive_array() {
     assert_eq!(acc.array[3], 15);
 }
 
+#[test]
+fn primitive_array_slice_api() {
+    const FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("first", PrimitiveField(0)),
+                                                                      ("array", ArrayField(16, 16)),
+                                                                      ("last", PrimitiveField(144))];
+    let layout = PrimitiveArray::load_layout(&FIELDS).unwrap();
+    let mut bytes = [0u8; 148];
+    let mut acc = layout.accessor(&mut bytes);
+
+    assert_eq!(acc.array.len(), 8);
+    assert!(acc.array.get(8).is_none());
+    acc.array.copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(acc.array.to_vec(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(acc.array.get(2), Some(&3));
+    *acc.array.get_mut(2).unwrap() = 30;
+    assert_eq!(acc.array[2], 30);
+
+    for (i, value) in acc.array.iter_mut().enumerate() {
+        *value += i as i32;
+    }
+    assert_eq!(acc.array.iter().rev().cloned().collect::<Vec<_>>(), vec![15, 13, 11, 9, 7, 32, 3, 1]);
+    assert_eq!(acc.array.iter().len(), 8);
+
+    {
+        let mut middle = acc.array.slice_mut(2..5);
+        assert_eq!(middle.len(), 3);
+        assert_eq!(middle[0], 32);
+        middle.fill(0);
+    }
+    assert_eq!(acc.array.to_vec(), vec![1, 3, 0, 0, 0, 11, 13, 15]);
+    assert_eq!(acc.array.slice_mut(6..).to_vec(), vec![13, 15]);
+    assert_eq!(acc.array.slice_mut(..=1).to_vec(), vec![1, 3]);
+
+    // Read-only views only need a shared borrow
+    let array = &acc.array;
+    let tail = array.slice(5..);
+    assert_eq!(tail.len(), 3);
+    assert_eq!(tail[1], 13);
+    assert!(tail.get(3).is_none());
+    assert_eq!(tail.iter().cloned().collect::<Vec<_>>(), vec![11, 13, 15]);
+    assert_eq!(array.slice(..2).to_vec(), vec![1, 3]);
+    assert_eq!(bytes[16 + 5 * 16], 11);
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn primitive_array_slice_out_of_bounds() {
+    let layout = make_primitive_array_layout();
+    let mut bytes = [0u8; 40];
+    let mut acc = layout.accessor(&mut bytes);
+    acc.array.slice_mut(4..9);
+}
+
 #[test]
 fn matrix_array() {
     let layout = make_matrix_array_layout();
@@ -280,21 +562,30 @@ fn matrix_array() {
                                  [121.0, 122.0, 123.0],
                                  [221.0, 222.0, 223.0]];
     let bytes: &mut [u8] = unsafe { &mut *(&mut ma as *mut [[f32; 3]; 4] as *mut [u8; 48]) };
-    let acc = layout.accessor(bytes);
+    let mut acc = layout.accessor(bytes);
 
-    assert_eq!(acc.array[0][0][0], 111.0);
-    assert_eq!(acc.array[0][0][1], 112.0);
-    assert_eq!(acc.array[0][0][2], 113.0);
-    assert_eq!(acc.array[0][1][0], 121.0);
-    assert_eq!(acc.array[0][1][1], 122.0);
-    assert_eq!(acc.array[0][1][2], 123.0);
+    assert_eq!(acc.array.index(0)[0][0], 111.0);
+    assert_eq!(acc.array.index(0)[0][1], 112.0);
+    assert_eq!(acc.array.index(0)[0][2], 113.0);
+    assert_eq!(acc.array.index(0)[1][0], 121.0);
+    assert_eq!(acc.array.index(0)[1][1], 122.0);
+    assert_eq!(acc.array.index(0)[1][2], 123.0);
 
-    assert_eq!(acc.array[1][0][0], 211.0);
-    assert_eq!(acc.array[1][0][1], 212.0);
-    assert_eq!(acc.array[1][0][2], 213.0);
-    assert_eq!(acc.array[1][1][0], 221.0);
-    assert_eq!(acc.array[1][1][1], 222.0);
-    assert_eq!(acc.array[1][1][2], 223.0);
+    assert_eq!(acc.array.index(1)[0][0], 211.0);
+    assert_eq!(acc.array.index(1)[0][1], 212.0);
+    assert_eq!(acc.array.index(1)[0][2], 213.0);
+    assert_eq!(acc.array.index(1)[1][0], 221.0);
+    assert_eq!(acc.array.index(1)[1][1], 222.0);
+    assert_eq!(acc.array.index(1)[1][2], 223.0);
+
+    assert_eq!(acc.array.len(), 2);
+    assert!(acc.array.get(2).is_none());
+    for (i, matrix) in acc.array.iter_mut().enumerate() {
+        matrix[1][0] = i as f32;
+    }
+    assert_eq!(acc.array.iter_mut().rev().map(|m| m[1][0]).collect::<Vec<_>>(), vec![1.0, 0.0]);
+    assert_eq!(ma[2][0], 0.0);
+    assert_eq!(ma[3][0], 1.0);
 }
 
 #[test]
@@ -310,15 +601,1314 @@ fn struct_array() {
         }]
     };
     let bytes: &mut [u8] = unsafe { &mut *(&mut sa as *mut StructArray as *mut [u8; 40]) };
-    let acc = layout.accessor(bytes);
-    assert_eq!(*acc.array[0].one, 0.0);
-    assert_eq!(*acc.array[1].one, 1.0);
-    assert_eq!(acc.array[0].four.x, 0.0);
-    assert_eq!(acc.array[0].four.y, 1.0);
-    assert_eq!(acc.array[0].four.z, 2.0);
-    assert_eq!(acc.array[0].four.w, 3.0);
-    assert_eq!(acc.array[1].four.x, 10.0);
-    assert_eq!(acc.array[1].four.y, 11.0);
-    assert_eq!(acc.array[1].four.z, 12.0);
-    assert_eq!(acc.array[1].four.w, 13.0);
+    let mut acc = layout.accessor(bytes);
+    assert_eq!(*acc.array.index(0).one, 0.0);
+    assert_eq!(*acc.array.index(1).one, 1.0);
+    assert_eq!({ acc.array.index(0).four.x }, 0.0);
+    assert_eq!({ acc.array.index(0).four.y }, 1.0);
+    assert_eq!({ acc.array.index(0).four.z }, 2.0);
+    assert_eq!({ acc.array.index(0).four.w }, 3.0);
+    assert_eq!({ acc.array.index(1).four.x }, 10.0);
+    assert_eq!({ acc.array.index(1).four.y }, 11.0);
+    assert_eq!({ acc.array.index(1).four.z }, 12.0);
+    assert_eq!({ acc.array.index(1).four.w }, 13.0);
+
+    for element in acc.array.iter_mut() {
+        *element.one += 5.0;
+    }
+    assert_eq!(acc.array.iter_mut().rev().map(|e| *e.one).collect::<Vec<_>>(), vec![6.0, 5.0]);
+}
+
+const QUX_ELEMENT_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("one", PrimitiveField(0)),
+                                                                            ("four", PrimitiveField(4))];
+const S_A_STRIDE_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("array",
+                                                                           StructArrayStrideField(0, 20, &QUX_ELEMENT_FIELDS))];
+
+#[test]
+fn strided_struct_array() {
+    let layout = StructArray::load_layout(&S_A_STRIDE_FIELDS).unwrap();
+    let mut bytes = [0u8; 40];
+    {
+        let mut acc = layout.accessor(&mut bytes);
+        assert_eq!(acc.array.len(), 2);
+        *acc.array.index(1).one = 1.0;
+        acc.array.index(1).four.w = 13.0;
+        assert!(acc.array.get(2).is_none());
+    }
+    let explicit_layout = make_struct_array_layout();
+    let mut expected = [0u8; 40];
+    {
+        let mut acc = explicit_layout.accessor(&mut expected);
+        *acc.array.index(1).one = 1.0;
+        acc.array.index(1).four.w = 13.0;
+    }
+    assert_eq!(&bytes[..], &expected[..]);
+
+    let spans: Vec<_> = <StructArray as LayoutDynamicField>::get_named_field_spans(&layout, "")
+        .collect();
+    let four = spans.iter().find(|s| s.path == "array[1].four").unwrap();
+    assert_eq!(four.span.offset, 24);
+    assert!(validate::<StructArray>(&layout).is_valid());
+}
+
+#[test]
+fn strided_struct_array_overflow() {
+    const FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("array",
+                                                                      StructArrayStrideField(0xFFFF_FFF0, 20, &QUX_ELEMENT_FIELDS))];
+    assert!(StructArray::load_layout(&FIELDS).is_err());
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Grid {
+    pub grid: [[f32; 3]; 2],
+    pub lights: [[Qux; 2]; 2],
+}
+
+const GRID_ROW: LayoutInfo<'static> = ArrayField(0, 4);
+const LIGHT_ROW: LayoutInfo<'static> = StructArrayStrideField(0, 20, &QUX_ELEMENT_FIELDS);
+const GRID_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("grid", ArrayOfArraysField(0, 12, &GRID_ROW)),
+                                                                      ("lights", ArrayOfArraysField(24, 40, &LIGHT_ROW))];
+
+#[test]
+fn arrays_of_arrays() {
+    let layout = Grid::load_layout(&GRID_FIELDS).unwrap();
+    let mut bytes = [0u8; 104];
+    {
+        let mut acc = layout.accessor(&mut bytes);
+        acc.grid[1][2] = 5.0;
+        acc.grid[0][1] = 3.0;
+        assert_eq!(acc.grid[1][2], 5.0);
+        assert_eq!(acc.grid.get(1).unwrap()[2], 5.0);
+        *acc.lights[1].index(0).one = 7.0;
+        assert_eq!(acc.grid.len(), 2);
+        assert!(acc.grid.get(2).is_none());
+        assert_eq!(acc.lights.iter_mut().count(), 2);
+    }
+    let floats: &[f32; 26] = unsafe { &*(&bytes as *const [u8; 104] as *const [f32; 26]) };
+    assert_eq!(floats[1], 3.0);
+    assert_eq!(floats[5], 5.0);
+    assert_eq!(floats[16], 7.0);
+
+    let spans: Vec<_> = <Grid as LayoutDynamicField>::get_named_field_spans(&layout, "").collect();
+    let paths: Vec<_> = spans.iter().map(|s| s.path.as_str()).collect();
+    assert_eq!(&paths[..7],
+               &["grid[0][0]", "grid[0][1]", "grid[0][2]", "grid[1][0]", "grid[1][1]",
+                 "grid[1][2]", "lights[0][0].one"]);
+    let last = spans.last().unwrap();
+    assert_eq!(last.path.as_str(), "lights[1][1].four");
+    assert_eq!(last.span.offset, 24 + 40 + 20 + 4);
+    assert!(validate::<Grid>(&layout).is_valid());
+
+    const TOO_LONG: &'static [(&'static str, LayoutInfo<'static>)] = &[("grid", ArrayOfArraysField(0xFFFF_FFF0, 12, &GRID_ROW)),
+                                                                        ("lights", ArrayOfArraysField(24, 40, &LIGHT_ROW))];
+    assert!(Grid::load_layout(&TOO_LONG).is_err());
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Particles {
+    pub count: u32,
+    pub particles: Unsized<Qux>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Weights {
+    pub weights: Unsized<f32>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Positions {
+    pub positions: Unsized<Vec3>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Transforms {
+    pub transforms: Unsized<Matrix2>,
+}
+
+#[test]
+fn runtime_sized_arrays() {
+    const PARTICLES: &'static [(&'static str, LayoutInfo<'static>)] = &[("count", PrimitiveField(0)),
+                                                                         ("particles", StructArrayStrideField(16, 32, &QUX_ELEMENT_FIELDS))];
+    let layout = Particles::load_layout(&PARTICLES).unwrap();
+    let mut bytes = vec![0u8; 16 + 32 * 3 + 8];
+    {
+        let mut acc = layout.accessor(&mut bytes);
+        assert_eq!(acc.particles.len(), 3);
+        acc.particles.index(2).four.x = 2.0;
+        assert!(acc.particles.get(3).is_none());
+    }
+    assert_eq!(&bytes[16 + 64 + 4..16 + 64 + 8], &[0, 0, 0, 64]);
+    let mut header_only = vec![0u8; 16];
+    assert_eq!(layout.accessor(&mut header_only).particles.len(), 0);
+
+    const WEIGHTS: &'static [(&'static str, LayoutInfo<'static>)] = &[("weights", ArrayField(0, 4))];
+    let layout = Weights::load_layout(&WEIGHTS).unwrap();
+    let mut bytes = vec![0u8; 22];
+    let mut acc = layout.accessor(&mut bytes);
+    acc.weights[4] = 1.0;
+    assert_eq!(acc.weights[4], 1.0);
+
+    const POSITIONS: &'static [(&'static str, LayoutInfo<'static>)] = &[("positions", ArrayField(0, 16))];
+    let layout = Positions::load_layout(&POSITIONS).unwrap();
+    let mut bytes = vec![0u8; 48];
+    let mut acc = layout.accessor(&mut bytes);
+    acc.positions[2].z = 1.0;
+    assert_eq!({ acc.positions[2].z }, 1.0);
+
+    // The last element does not need the padding after it
+    let mut bytes = vec![0u8; 16 * 2 + 12];
+    assert_eq!(layout.accessor(&mut bytes).positions.len(), 3);
+    let mut bytes = vec![0u8; 16 * 2 + 11];
+    assert_eq!(layout.accessor(&mut bytes).positions.len(), 2);
+
+    const TRANSFORMS: &'static [(&'static str, LayoutInfo<'static>)] = &[("transforms", MatrixArrayField(0, 16, 8))];
+    let layout = Transforms::load_layout(&TRANSFORMS).unwrap();
+    let mut bytes = vec![0u8; 50];
+    let mut acc = layout.accessor(&mut bytes);
+    assert_eq!(acc.transforms.len(), 3);
+    acc.transforms.index(2)[1][1] = 1.0;
+    assert_eq!(&bytes[44..48], &[0, 0, 128, 63]);
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn runtime_sized_array_out_of_bounds() {
+    const WEIGHTS: &'static [(&'static str, LayoutInfo<'static>)] = &[("weights", ArrayField(0, 4))];
+    let layout = Weights::load_layout(&WEIGHTS).unwrap();
+    let mut bytes = vec![0u8; 16];
+    let mut acc = layout.accessor(&mut bytes);
+    acc.weights[4] = 1.0;
+}
+
+const SHORT_ARRAY: LayoutInfo<'static> = ArrayField(4, 4);
+const P_A_SHORT_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("first", PrimitiveField(0)),
+                                                                           ("array", ArrayLength(4, &SHORT_ARRAY)),
+                                                                           ("last", PrimitiveField(20))];
+
+#[test]
+fn declared_array_length() {
+    const MATCHING: &'static [(&'static str, LayoutInfo<'static>)] = &[("first", PrimitiveField(0)),
+                                                                        ("array", ArrayLength(8, &SHORT_ARRAY)),
+                                                                        ("last", PrimitiveField(36))];
+    assert!(PrimitiveArray::load_layout(&MATCHING).is_ok());
+    assert!(PrimitiveArray::load_layout(&P_A_SHORT_FIELDS).is_err());
+
+    let options = LoadOptions { array_length: ArrayLengthPolicy::Truncate, ..LoadOptions::default() };
+    let layout = PrimitiveArray::load_layout_with_options(&P_A_SHORT_FIELDS, &options).unwrap();
+    let spans: Vec<_> = <PrimitiveArray as LayoutDynamicField>::get_field_spans(&layout).collect();
+    assert_eq!(spans.len(), 6);
+    assert_eq!(spans.iter().map(|s| s.offset + s.length).max(), Some(24));
+
+    let mut bytes = [0u8; 24];
+    let mut acc = layout.accessor(&mut bytes);
+    acc.array[3] = 1;
+    *acc.last = 2;
+    assert_eq!(acc.array[3], 1);
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn truncated_array_out_of_bounds() {
+    let options = LoadOptions { array_length: ArrayLengthPolicy::Truncate, ..LoadOptions::default() };
+    let layout = PrimitiveArray::load_layout_with_options(&P_A_SHORT_FIELDS, &options).unwrap();
+    let mut bytes = [0u8; 24];
+    let mut acc = layout.accessor(&mut bytes);
+    acc.array[4] = 1;
+}
+
+#[test]
+fn declared_struct_array_length() {
+    const ONE_ELEMENT: &'static [(&'static str, LayoutInfo<'static>)] = &[("array",
+                                                                           StructArrayField(&[&QUX_LAYOUT_0]))];
+    assert!(StructArray::load_layout(&ONE_ELEMENT).is_err());
+
+    let options = LoadOptions { array_length: ArrayLengthPolicy::Truncate, ..LoadOptions::default() };
+    let layout = StructArray::load_layout_with_options(&ONE_ELEMENT, &options).unwrap();
+    let mut bytes = [0u8; 40];
+    let mut acc = layout.accessor(&mut bytes);
+    assert_eq!(acc.array.len(), 1);
+    assert!(acc.array.get(1).is_none());
+
+    const STRIDED_ROW: LayoutInfo<'static> = StructArrayStrideField(0, 20, &QUX_ELEMENT_FIELDS);
+    const STRIDED: &'static [(&'static str, LayoutInfo<'static>)] = &[("array", ArrayLength(3, &STRIDED_ROW))];
+    assert!(StructArray::load_layout(&STRIDED).is_err());
+    let layout = StructArray::load_layout_with_options(&STRIDED, &options).unwrap();
+    let acc = layout.accessor(&mut bytes);
+    assert_eq!(acc.array.len(), 2);
+}
+
+fn make_foo() -> Foo {
+    Foo {
+        three: Vec3::new(1.0, 2.0, 3.0),
+        one: 4.0,
+        four: Vec4::new(5.0, 6.0, 7.0, 8.0),
+        two: Vec2::new(9.0, 10.0),
+        compound: Bar {
+            one: 11.0,
+            four: Vec4::new(12.0, 13.0, 14.0, 15.0),
+            matrix: Matrix4::new([[16.0, 17.0, 18.0, 19.0],
+                                  [20.0, 21.0, 22.0, 23.0],
+                                  [24.0, 25.0, 26.0, 27.0],
+                                  [28.0, 29.0, 30.0, 31.0]]),
+        },
+    }
+}
+
+#[test]
+fn dynamic_buffer() {
+    let mut buffer = DynamicBuffer::<Foo>::new(make_foo_layout());
+    assert_eq!(buffer.len(), FOO_SIZE);
+    assert!(buffer.as_bytes().iter().all(|&b| b == 0));
+
+    // The layout matches the packed Rust struct exactly
+    let foo = make_foo();
+    buffer.write(&foo);
+    let foo_bytes: &[u8; FOO_SIZE] = unsafe { &*(&foo as *const Foo as *const [u8; FOO_SIZE]) };
+    assert_eq!(buffer.as_bytes(), &foo_bytes[..]);
+
+    buffer.accessor().compound.four.w = 50.0;
+    let read = buffer.read();
+    assert_eq!({ read.compound.four.w }, 50.0);
+    assert_eq!({ read.two.y }, 10.0);
+
+    const SPREAD_BAR_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("one", PrimitiveField(48)),
+                                                                                 ("four", PrimitiveField(64)),
+                                                                                 ("matrix", ArrayField(80, 16))];
+    const SPREAD_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("three", PrimitiveField(0)),
+                                                                             ("one", PrimitiveField(12)),
+                                                                             ("four", PrimitiveField(16)),
+                                                                             ("two", PrimitiveField(32)),
+                                                                             ("compound", StructField(&SPREAD_BAR_FIELDS))];
+    buffer.set_layout(Foo::load_layout(&SPREAD_FIELDS).unwrap());
+    assert_eq!(buffer.len(), 144);
+    let read = buffer.read();
+    assert_eq!({ read.compound.four.w }, 50.0);
+    let matrix = read.compound.matrix;
+    assert_eq!(matrix[3][3], 31.0);
+    assert_eq!(&buffer.as_bytes()[140..144], &31.0f32.to_bits().to_le_bytes());
+}
+
+#[test]
+fn dynamic_buffer_arrays() {
+    let mut buffer = DynamicBuffer::<Grid>::load(&GRID_FIELDS).unwrap();
+    let qux = |one: f32| Qux { one: one, four: Vec4::new(one, one, one, one) };
+    buffer.write(&Grid {
+        grid: [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]],
+        lights: [[qux(7.0), qux(8.0)], [qux(9.0), qux(10.0)]],
+    });
+    let read = buffer.read();
+    let grid = read.grid;
+    assert_eq!(grid, [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+    let lights = read.lights;
+    assert_eq!({ lights[1][0].one }, 9.0);
+    assert_eq!({ lights[1][1].four.z }, 10.0);
+
+    let mut buffer = DynamicBuffer::<MatrixArray>::load(&M_A_FIELDS).unwrap();
+    buffer.write(&MatrixArray {
+        array: [Matrix2x3::new([[111.0, 112.0, 113.0], [121.0, 122.0, 123.0]]),
+                Matrix2x3::new([[211.0, 212.0, 213.0], [221.0, 222.0, 223.0]])],
+    });
+    let floats: Vec<f32> = buffer.as_bytes()
+        .chunks(4)
+        .map(|b| f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+        .collect();
+    assert_eq!(floats, vec![111.0, 112.0, 113.0, 211.0, 212.0, 213.0, 121.0, 122.0, 123.0, 221.0, 222.0, 223.0]);
+    let array = buffer.read().array;
+    assert_eq!(array[1].columns()[1][0], 221.0);
+
+    // Elements missing from a truncated layout read as zero
+    const ONE_ELEMENT: &'static [(&'static str, LayoutInfo<'static>)] = &[("array",
+                                                                           StructArrayField(&[&QUX_LAYOUT_0]))];
+    let options = LoadOptions { array_length: ArrayLengthPolicy::Truncate, ..LoadOptions::default() };
+    let mut buffer = DynamicBuffer::<StructArray>::new(StructArray::load_layout_with_options(&ONE_ELEMENT, &options)
+                                                           .unwrap());
+    assert_eq!(buffer.len(), 20);
+    buffer.write(&StructArray { array: [qux(1.0), qux(2.0)] });
+    let array = buffer.read().array;
+    assert_eq!({ array[0].four.w }, 1.0);
+    assert_eq!({ array[1].one }, 0.0);
+
+    // A buffer grown for more runtime array elements keeps its size across layout changes
+    const PARTICLES: &'static [(&'static str, LayoutInfo<'static>)] = &[("count", PrimitiveField(0)),
+                                                                         ("particles", StructArrayStrideField(16, 32, &QUX_ELEMENT_FIELDS))];
+    let mut buffer = DynamicBuffer::<Particles>::load(&PARTICLES).unwrap();
+    buffer.resize(16 + 32 * 3);
+    assert_eq!(buffer.accessor().particles.len(), 3);
+    buffer.set_layout(Particles::load_layout(&PARTICLES).unwrap());
+    assert_eq!(buffer.len(), 16 + 32 * 3);
+    assert_eq!(buffer.accessor().particles.len(), 3);
+}
+
+#[test]
+fn dirty_ranges() {
+    let mut buffer = DynamicBuffer::<Foo>::new(make_foo_layout());
+    let options = CoalesceOptions::default();
+    assert_eq!(buffer.take_dirty_ranges(&options),
+               vec![FieldSpan { offset: 0, length: FOO_SIZE as LengthType }]);
+
+    buffer.set_dirty_tracking(true);
+    assert_eq!(buffer.take_dirty_ranges(&options).len(), 1);
+    assert_eq!(buffer.take_dirty_ranges(&options), vec![]);
+
+    *buffer.accessor().one = 1.0;
+    assert_eq!(buffer.take_dirty_ranges(&options), vec![FieldSpan { offset: 12, length: 4 }]);
+    assert_eq!(buffer.take_dirty_ranges(&options), vec![]);
+
+    {
+        let acc = buffer.accessor();
+        acc.two.y = 2.0;
+        acc.compound.four.x = 3.0;
+        acc.compound.matrix[3][0] = 4.0;
+    }
+    assert_eq!(buffer.take_dirty_ranges(&options),
+               vec![FieldSpan { offset: 32, length: 8 },
+                    FieldSpan { offset: 44, length: 16 },
+                    FieldSpan { offset: 108, length: 16 }]);
+
+    // Writing the same values again does not make anything dirty
+    let value = buffer.read();
+    buffer.write(&value);
+    assert_eq!(buffer.take_dirty_ranges(&options), vec![]);
+
+    buffer.accessor().two.x = 5.0;
+    *buffer.accessor().compound.one = 6.0;
+    assert_eq!(buffer.take_dirty_ranges(&CoalesceOptions { max_gap: 4, ..Default::default() }),
+               vec![FieldSpan { offset: 32, length: 12 }]);
+
+    buffer.mark_all_dirty();
+    assert_eq!(buffer.take_dirty_ranges(&options),
+               vec![FieldSpan { offset: 0, length: FOO_SIZE as LengthType }]);
+}
+
+#[test]
+fn dirty_runtime_sized_array() {
+    const WEIGHTS: &'static [(&'static str, LayoutInfo<'static>)] = &[("weights", ArrayField(0, 4))];
+    let mut buffer = DynamicBuffer::<Weights>::with_size(Weights::load_layout(&WEIGHTS).unwrap(), 16);
+    buffer.set_dirty_tracking(true);
+    assert_eq!(buffer.take_dirty_ranges(&CoalesceOptions::default()),
+               vec![FieldSpan { offset: 0, length: 16 }]);
+    buffer.accessor().weights[2] = 1.0;
+    assert_eq!(buffer.take_dirty_ranges(&CoalesceOptions::default()),
+               vec![FieldSpan { offset: 0, length: 16 }]);
+    assert_eq!(buffer.take_dirty_ranges(&CoalesceOptions::default()), vec![]);
+}
+
+#[test]
+fn diff_buffers() {
+    let layout = make_foo_layout();
+    let old = vec![0u8; FOO_SIZE + 4];
+    let mut new = old.clone();
+    {
+        let acc = layout.accessor(&mut new);
+        *acc.one = 1.5;
+        acc.two.y = -2.0;
+        acc.compound.matrix[2][1] = 3.0;
+    }
+    // Bytes outside of fields are padding
+    new[FOO_SIZE] = 1;
+
+    let changes = diff::<Foo>(&layout, &old, &new);
+    let paths: Vec<_> = changes.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(paths, vec!["one", "two", "compound.matrix[2]"]);
+    assert_eq!(changes[0].new.as_str(), "1.5");
+    assert_eq!(changes[1].type_name, "Vec2");
+    assert_eq!(changes[1].old.as_str(), "[0.0, 0.0]");
+    assert_eq!(changes[1].new.as_str(), "[0.0, -2.0]");
+    assert_eq!(changes[2].span, FieldSpan { offset: 92, length: 16 });
+    assert!(diff::<Foo>(&layout, &old, &old).is_empty());
+
+    let layout = make_primitive_array_layout();
+    let mut old = [0u8; 40];
+    let mut new = [0u8; 40];
+    layout.accessor(&mut new).array[5] = -7;
+    let changes = diff::<PrimitiveArray>(&layout, &old, &new);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path.as_str(), "array[5]");
+    assert_eq!(changes[0].old.as_str(), "0");
+    assert_eq!(changes[0].new.as_str(), "-7");
+    old[0] = 1;
+    let changes = diff::<PrimitiveArray>(&layout, &old, &new);
+    assert_eq!(changes[0].path.as_str(), "first");
+    assert_eq!(changes[0].old.as_str(), "1");
+}
+
+#[test]
+fn block_arena() {
+    let mut arena = BlockArena::<Foo>::new(make_foo_layout(), 0, 256, 3, ArenaMode::Linear).unwrap();
+    assert_eq!(arena.slot_stride(), 256);
+    assert_eq!(arena.as_bytes().len(), 768);
+
+    let first = arena.allocate().unwrap();
+    let second = arena.allocate().unwrap();
+    assert_eq!(first, ArenaSlot { index: 0, offset: 0, size: FOO_SIZE as LengthType });
+    assert_eq!(second.offset, 256);
+    *arena.accessor(second).one = 1.0;
+    arena.write(first, &make_foo());
+    assert_eq!(&arena.as_bytes()[256 + 12..256 + 16], &1.0f32.to_ne_bytes());
+    assert_eq!(&arena.as_bytes()[120..124], &31.0f32.to_ne_bytes());
+    assert_eq!(arena.as_bytes()[124], 0);
+
+    assert!(arena.allocate().is_some());
+    assert!(arena.allocate().is_none());
+    assert_eq!(arena.len(), 3);
+    arena.reset();
+    assert_eq!(arena.allocate(), Some(first));
+
+    let mut ring = BlockArena::<Foo>::new(make_foo_layout(), 0, 64, 2, ArenaMode::Ring).unwrap();
+    let offsets: Vec<_> = (0..5).map(|_| ring.allocate().unwrap().offset).collect();
+    assert_eq!(offsets, vec![0, 128, 0, 128, 0]);
+
+    assert!(BlockArena::<Foo>::new(make_foo_layout(), 0, 256, 1 << 24, ArenaMode::Linear).is_err());
+
+    // Slots cover the whole block data, also the padding after the last field
+    let data_size = Std140.size(&<Padded as DescribeType>::TYPE_INFO);
+    let layout = Padded::load_layout(&compute_layout::<Padded, _>(&Std140).unwrap()).unwrap();
+    assert_eq!(layout_size::<Padded>(&layout), 40);
+    let mut arena = BlockArena::<Padded>::new(layout, data_size, 16, 2, ArenaMode::Linear).unwrap();
+    assert_eq!(arena.allocate().unwrap().size, 48);
+    assert_eq!(arena.slot_stride(), 48);
+
+    let layout = Padded::load_layout(&compute_layout::<Padded, _>(&Std140).unwrap()).unwrap();
+    let mut buffer = DynamicBuffer::<Padded>::with_size(layout, data_size);
+    assert_eq!(buffer.len(), 48);
+    buffer.set_layout(Padded::load_layout(&compute_layout::<Padded, _>(&Std140).unwrap()).unwrap());
+    assert_eq!(buffer.len(), 48);
+}
+
+fn member(name: &str, member_type: GlslType, offset: u32) -> BlockMember {
+    BlockMember::new(name, member_type, offset)
+}
+
+fn array_member(name: &str, member_type: GlslType, offset: u32, size: usize, stride: u32) -> BlockMember {
+    BlockMember { array_size: size, array_stride: stride, ..BlockMember::new(name, member_type, offset) }
+}
+
+#[test]
+fn introspected_layout() {
+    let mut program = MockProgram::new();
+    program.add_block("Foo",
+                      vec![member("three", GlslType::Vector(ScalarType::Float, 3), 0),
+                           member("one", GlslType::Scalar(ScalarType::Float), 12),
+                           member("four", GlslType::Vector(ScalarType::Float, 4), 16),
+                           member("two", GlslType::Vector(ScalarType::Float, 2), 32),
+                           member("compound.one", GlslType::Scalar(ScalarType::Float), 40),
+                           member("compound.four", GlslType::Vector(ScalarType::Float, 4), 44),
+                           BlockMember { matrix_stride: 16,
+                                         ..member("compound.matrix", GlslType::Matrix { columns: 4, rows: 4 }, 60) }]);
+    program.add_block("Grid",
+                      vec![array_member("grid[0][0]", GlslType::Scalar(ScalarType::Float), 0, 3, 4),
+                           array_member("grid[1][0]", GlslType::Scalar(ScalarType::Float), 12, 3, 4),
+                           member("lights[0][0].one", GlslType::Scalar(ScalarType::Float), 24),
+                           member("lights[0][0].four", GlslType::Vector(ScalarType::Float, 4), 28),
+                           member("lights[0][1].one", GlslType::Scalar(ScalarType::Float), 44),
+                           member("lights[0][1].four", GlslType::Vector(ScalarType::Float, 4), 48),
+                           member("lights[1][0].one", GlslType::Scalar(ScalarType::Float), 64),
+                           member("lights[1][0].four", GlslType::Vector(ScalarType::Float, 4), 68),
+                           member("lights[1][1].one", GlslType::Scalar(ScalarType::Float), 84),
+                           member("lights[1][1].four", GlslType::Vector(ScalarType::Float, 4), 88)]);
+    program.add_block("PrimitiveArray",
+                      vec![member("first", GlslType::Scalar(ScalarType::Int), 0),
+                           array_member("array[0]", GlslType::Scalar(ScalarType::Int), 4, 4, 4),
+                           member("last", GlslType::Scalar(ScalarType::Int), 20)]);
+    program.add_block("MatrixArray",
+                      vec![BlockMember { matrix_stride: 24,
+                                         ..array_member("array[0]", GlslType::Matrix { columns: 2, rows: 3 }, 0, 2, 12) }]);
+    assert_eq!(program.active_blocks(), vec!["Foo", "Grid", "PrimitiveArray", "MatrixArray"]);
+
+    let block = block_layout(&program, "Foo").unwrap();
+    let layout = Foo::load_layout(&block).unwrap();
+    let expected: Vec<_> = <Foo as LayoutDynamicField>::get_field_spans(&make_foo_layout()).collect();
+    assert_eq!(<Foo as LayoutDynamicField>::get_field_spans(&layout).collect::<Vec<_>>(), expected);
+
+    let block = block_layout(&program, "Grid").unwrap();
+    let layout = Grid::load_layout(&block).unwrap();
+    let expected: Vec<_> = <Grid as LayoutDynamicField>::get_field_spans(&Grid::load_layout(&GRID_FIELDS).unwrap())
+        .collect();
+    assert_eq!(<Grid as LayoutDynamicField>::get_field_spans(&layout).collect::<Vec<_>>(), expected);
+
+    let block = block_layout(&program, "MatrixArray").unwrap();
+    let layout = MatrixArray::load_layout(&block).unwrap();
+    let expected: Vec<_> = <MatrixArray as LayoutDynamicField>::get_field_spans(&make_matrix_array_layout())
+        .collect();
+    assert_eq!(<MatrixArray as LayoutDynamicField>::get_field_spans(&layout).collect::<Vec<_>>(), expected);
+
+    // Buffer blocks only list the first element of a top-level array, with its stride
+    program.add_block("Particles",
+                      vec![member("count", GlslType::Scalar(ScalarType::Uint), 0),
+                           BlockMember { top_level_array_stride: 32,
+                                         ..member("particles[0].one", GlslType::Scalar(ScalarType::Float), 16) },
+                           BlockMember { top_level_array_stride: 32,
+                                         ..member("particles[0].four", GlslType::Vector(ScalarType::Float, 4), 32) }]);
+    let block = block_layout(&program, "Particles").unwrap();
+    let layout = Particles::load_layout(&block).unwrap();
+    let mut bytes = vec![0u8; 16 + 32 * 3];
+    {
+        let mut acc = layout.accessor(&mut bytes);
+        assert_eq!(acc.particles.len(), 3);
+        acc.particles.index(2).four.x = 2.0;
+    }
+    assert_eq!(&bytes[16 + 64 + 16..16 + 64 + 20], &[0, 0, 0, 64]);
+
+    // The shader declares fewer elements than the Rust struct
+    let block = block_layout(&program, "PrimitiveArray").unwrap();
+    assert!(PrimitiveArray::load_layout(&block).is_err());
+    let options = LoadOptions { array_length: ArrayLengthPolicy::Truncate, ..LoadOptions::default() };
+    assert!(PrimitiveArray::load_layout_with_options(&block, &options).is_ok());
+
+    assert!(block_layout(&program, "Missing").is_err());
+}
+
+#[test]
+fn introspected_layout_errors() {
+    let mut program = MockProgram::new();
+    program.add_block("RowMajor",
+                      vec![BlockMember { matrix_stride: 16,
+                                         row_major: true,
+                                         ..member("matrix", GlslType::Matrix { columns: 4, rows: 4 }, 0) }]);
+    program.add_block("BadName", vec![member("a[x]", GlslType::Scalar(ScalarType::Float), 0)]);
+    program.add_block("Duplicate",
+                      vec![member("a", GlslType::Scalar(ScalarType::Float), 0),
+                           member("a", GlslType::Scalar(ScalarType::Float), 4)]);
+    program.add_block("Gap",
+                      vec![member("s[0].a", GlslType::Scalar(ScalarType::Float), 0),
+                           member("s[2].a", GlslType::Scalar(ScalarType::Float), 8)]);
+    program.add_block("Uneven",
+                      vec![member("s[0].a", GlslType::Scalar(ScalarType::Float), 0),
+                           member("s[1].a", GlslType::Scalar(ScalarType::Float), 16),
+                           member("s[2].a", GlslType::Scalar(ScalarType::Float), 48)]);
+    for name in &["RowMajor", "BadName", "Duplicate", "Gap", "Uneven"] {
+        assert!(block_layout(&program, name).is_err(), "{}", name);
+    }
+    let error = block_layout(&program, "Gap").err().unwrap();
+    assert_eq!(error.path, "s[1]");
+    let error = block_layout(&program, "Uneven").err().unwrap();
+    assert_eq!(error.path, "s[2]");
+    assert_eq!(error.kind, LayoutErrorKind::InvalidMember("elements are not evenly spaced".to_string()));
+    let error = block_layout(&program, "Missing").err().unwrap();
+    assert_eq!(error.kind, LayoutErrorKind::MissingBlock("Missing".to_string()));
+    assert_eq!(GlslType::from_gl_enum(0x8B6A), Some(GlslType::Matrix { columns: 4, rows: 3 }));
+    assert_eq!(GlslType::from_gl_enum(0x8DC7), Some(GlslType::Vector(ScalarType::Uint, 3)));
+}
+
+const IVEC2: GlslType = GlslType::Vector(ScalarType::Int, 2);
+const VEC4: GlslType = GlslType::Vector(ScalarType::Float, 4);
+
+#[test]
+fn typed_layouts() {
+    const FOUR: LayoutInfo<'static> = PrimitiveField(44);
+    const MATRIX: LayoutInfo<'static> = ArrayField(60, 16);
+    const TYPED_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] =
+        &[("one", PrimitiveField(40)),
+          ("four", Typed(VEC4, &FOUR)),
+          ("matrix", Typed(GlslType::Matrix { columns: 4, rows: 4 }, &MATRIX))];
+    assert!(Bar::load_layout(&TYPED_FIELDS).is_ok());
+
+    const MISTYPED_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] =
+        &[("one", PrimitiveField(40)), ("four", Typed(IVEC2, &FOUR)), ("matrix", MATRIX)];
+    let error = Bar::load_layout(&MISTYPED_FIELDS).err().unwrap();
+    let message = error.to_string();
+    assert_eq!(message, "four: expected vec4 but the shader declares ivec2");
+    assert_eq!(error,
+               LayoutError {
+                   path: "four".to_string(),
+                   kind: LayoutErrorKind::TypeMismatch {
+                       expected: VEC4,
+                       found: IVEC2,
+                   },
+               });
+
+    // Tags of arrays describe the elements and may sit under the declared length
+    const INTS: LayoutInfo<'static> = ArrayField(4, 4);
+    const TYPED_INTS: LayoutInfo<'static> = Typed(GlslType::Scalar(ScalarType::Int), &INTS);
+    const TYPED_ARRAY: &'static [(&'static str, LayoutInfo<'static>)] =
+        &[("first", PrimitiveField(0)), ("array", ArrayLength(8, &TYPED_INTS)), ("last", PrimitiveField(36))];
+    assert!(PrimitiveArray::load_layout(&TYPED_ARRAY).is_ok());
+    const FLOATS: LayoutInfo<'static> = Typed(GlslType::Scalar(ScalarType::Float), &INTS);
+    const MISTYPED_ARRAY: &'static [(&'static str, LayoutInfo<'static>)] =
+        &[("first", PrimitiveField(0)), ("array", FLOATS), ("last", PrimitiveField(36))];
+    let error = PrimitiveArray::load_layout(&MISTYPED_ARRAY).err().unwrap();
+    assert_eq!(error.path, "array");
+
+    // Errors in nested structs have the full path
+    const NESTED_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] =
+        &[("one", PrimitiveField(40)), ("four", PrimitiveField(44))];
+    const NESTED: &'static [(&'static str, LayoutInfo<'static>)] =
+        &[("three", PrimitiveField(0)),
+          ("one", PrimitiveField(12)),
+          ("four", PrimitiveField(16)),
+          ("two", ArrayField(32, 4)),
+          ("compound", StructField(&NESTED_FIELDS))];
+    let message = Foo::load_layout(&NESTED).err().unwrap().to_string();
+    assert_eq!(message, "two: expected primitive layout, found array");
+    const NESTED_MISSING: &'static [(&'static str, LayoutInfo<'static>)] =
+        &[("three", PrimitiveField(0)),
+          ("one", PrimitiveField(12)),
+          ("four", PrimitiveField(16)),
+          ("two", PrimitiveField(32)),
+          ("compound", StructField(&NESTED_FIELDS))];
+    let error = Foo::load_layout(&NESTED_MISSING).err().unwrap();
+    assert_eq!(error.path, "compound.matrix");
+    assert_eq!(error.kind, LayoutErrorKind::MissingField);
+
+    // Introspected layouts carry the declared types
+    let mut program = MockProgram::new();
+    program.add_block("Qux", vec![member("one", GlslType::Scalar(ScalarType::Float), 0), member("four", IVEC2, 4)]);
+    let block = block_layout(&program, "Qux").unwrap();
+    let error = Qux::load_layout(&block).err().unwrap();
+    assert_eq!(error.path, "four");
+}
+
+const PARTIAL_BAR_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("one", PrimitiveField(40)),
+                                                                             ("four", PrimitiveField(44))];
+const PARTIAL_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("three", PrimitiveField(0)),
+                                                                         ("one", PrimitiveField(12)),
+                                                                         ("four", PrimitiveField(16)),
+                                                                         ("extra", PrimitiveField(32)),
+                                                                         ("compound", StructField(&PARTIAL_BAR_FIELDS))];
+
+const QUX_PARTIAL: &'static [(&'static str, LayoutInfo<'static>)] = &[("one", PrimitiveField(20))];
+const QUX_PARTIAL_LAYOUT: LayoutInfo<'static> = StructField(&QUX_PARTIAL);
+const PARTIAL_ARRAY: &'static [(&'static str, LayoutInfo<'static>)] =
+    &[("array", StructArrayField(&[&QUX_LAYOUT_0, &QUX_PARTIAL_LAYOUT]))];
+
+#[test]
+fn unmatched_fields() {
+    assert!(match_fields::<Foo>(&FOO_FIELDS).is_exact());
+    assert_eq!(match_fields::<Foo>(&PARTIAL_FIELDS),
+               FieldReport {
+                   rust_only: vec!["two".to_string(), "compound.matrix".to_string()],
+                   shader_only: vec!["extra".to_string()],
+               });
+    let report = match_fields::<StructArray>(&PARTIAL_ARRAY);
+    assert_eq!(report.rust_only, vec!["array[1].four".to_string()]);
+
+    // The default ignores members only the shader has
+    assert!(Foo::load_layout(&PARTIAL_FIELDS).is_err());
+    const EXTRA_FIELDS: &'static [(&'static str, LayoutInfo<'static>)] = &[("one", PrimitiveField(40)),
+                                                                           ("four", PrimitiveField(44)),
+                                                                           ("matrix", ArrayField(60, 16)),
+                                                                           ("extra", PrimitiveField(124))];
+    assert!(Bar::load_layout(&EXTRA_FIELDS).is_ok());
+
+    let strict = LoadOptions { fields: FieldMatching::Strict, ..LoadOptions::default() };
+    assert!(Foo::load_layout_with_options(&FOO_FIELDS, &strict).is_ok());
+    let error = Bar::load_layout_with_options(&EXTRA_FIELDS, &strict).err().unwrap();
+    assert_eq!(error.path, "extra");
+    assert_eq!(error.kind, LayoutErrorKind::UnmatchedMember);
+}
+
+#[test]
+fn lenient_loading() {
+    let lenient = LoadOptions { fields: FieldMatching::Lenient, ..LoadOptions::default() };
+    let layout = Foo::load_layout_with_options(&PARTIAL_FIELDS, &lenient).unwrap();
+    assert!(validate::<Foo>(&layout).is_valid());
+
+    // The missing fields are absent and take no space
+    let spans: Vec<_> = <Foo as LayoutDynamicField>::get_named_field_spans(&layout, "")
+        .map(|named| (named.path, named.span.offset))
+        .collect();
+    assert_eq!(spans,
+               expected_offsets(&[("three", 0), ("one", 12), ("four", 16), ("compound.one", 40), ("compound.four", 44)]));
+    assert_eq!(layout_size::<Foo>(&layout), 60);
+
+    let mut buffer = DynamicBuffer::<Foo>::new(layout);
+    assert_eq!(buffer.len(), 60);
+    buffer.write(&make_foo());
+    let read = buffer.read();
+    assert_eq!({ read.compound.four.w }, 15.0);
+    assert_eq!({ read.two.y }, 0.0);
+    assert_eq!({ read.compound.matrix.columns()[3][3] }, 0.0);
+    assert_eq!(&buffer.as_bytes()[44..48], &12.0f32.to_bits().to_le_bytes());
+
+    // Elements of an array of structs can lack different fields
+    let layout = StructArray::load_layout_with_options(&PARTIAL_ARRAY, &lenient).unwrap();
+    let spans: Vec<_> = <StructArray as LayoutDynamicField>::get_named_field_spans(&layout, "")
+        .map(|named| (named.path, named.span.offset))
+        .collect();
+    assert_eq!(spans,
+               expected_offsets(&[("array[0].one", 0), ("array[0].four", 4), ("array[1].one", 20)]));
+    assert_eq!(layout_size::<StructArray>(&layout), 24);
+
+    // Nothing is moved if all fields are there
+    let layout = Foo::load_layout_with_options(&FOO_FIELDS, &lenient).unwrap();
+    let expected: Vec<_> = <Foo as LayoutDynamicField>::get_field_spans(&make_foo_layout()).collect();
+    assert_eq!(<Foo as LayoutDynamicField>::get_field_spans(&layout).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+#[should_panic(expected = "`two`")]
+fn lenient_accessor_of_absent_field() {
+    let lenient = LoadOptions { fields: FieldMatching::Lenient, ..LoadOptions::default() };
+    let layout = Foo::load_layout_with_options(&PARTIAL_FIELDS, &lenient).unwrap();
+    let mut bytes = [0u8; 60];
+    layout.accessor(&mut bytes);
+}
+
+#[test]
+fn glsl_declarations() {
+    let foo = block_declaration::<Foo>(BlockKind::Uniform, Packing::Std140, Some("foo")).unwrap();
+    assert_eq!(foo,
+               "struct Bar {\n    float one;\n    vec4 four;\n    mat4 matrix;\n};\n\n\
+                layout(std140) uniform Foo {\n    vec3 three;\n    float one;\n    vec4 four;\n    \
+                vec2 two;\n    Bar compound;\n} foo;\n");
+
+    // Each struct is declared once, before it is used
+    let grid = block_declaration::<Grid>(BlockKind::Uniform, Packing::Shared, None).unwrap();
+    assert_eq!(grid,
+               "struct Qux {\n    float one;\n    vec4 four;\n};\n\n\
+                layout(shared) uniform Grid {\n    float grid[2][3];\n    Qux lights[2][2];\n};\n");
+
+    let matrices = block_declaration::<MatrixArray>(BlockKind::Uniform, Packing::Packed, None).unwrap();
+    assert_eq!(matrices, "layout(packed) uniform MatrixArray {\n    mat2x3 array[2];\n};\n");
+
+    let particles = block_declaration::<Particles>(BlockKind::Buffer, Packing::Std430, Some("data")).unwrap();
+    assert_eq!(particles,
+               "struct Qux {\n    float one;\n    vec4 four;\n};\n\n\
+                layout(std430) buffer Particles {\n    uint count;\n    Qux particles[];\n} data;\n");
+
+    let error = block_declaration::<Particles>(BlockKind::Uniform, Packing::Std430, None).err().unwrap();
+    assert_eq!(error.kind, LayoutErrorKind::UnsupportedPacking("std430"));
+    let error = block_declaration::<Vec4>(BlockKind::Uniform, Packing::Std140, None).err().unwrap();
+    assert_eq!(error.kind, LayoutErrorKind::UnsupportedType);
+
+    // Runtime-sized arrays can only end a buffer block
+    let error = block_declaration::<Particles>(BlockKind::Uniform, Packing::Std140, None).err().unwrap();
+    assert_eq!(error, LayoutError { path: "particles".to_string(), kind: LayoutErrorKind::UnsupportedType });
+    let error = block_declaration::<MisplacedArray>(BlockKind::Buffer, Packing::Std430, None).err().unwrap();
+    assert_eq!(error, LayoutError { path: "items".to_string(), kind: LayoutErrorKind::UnsupportedType });
+    let error = block_declaration::<NestedRuntimeArray>(BlockKind::Buffer, Packing::Std430, None).err().unwrap();
+    assert_eq!(error, LayoutError { path: "particles".to_string(), kind: LayoutErrorKind::UnsupportedType });
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct MisplacedArray {
+    pub items: Unsized<f32>,
+    pub count: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct NestedRuntimeArray {
+    pub particles: Particles,
+}
+
+const SHADER: &'static str = "#version 430
+#define LIGHT_COUNT 4
+
+struct Light {
+    vec3 position; // world space
+    float radius;
+    mat4x3 transform;
+};
+
+/* Not a block */
+uniform sampler2D shadows;
+
+layout(std140, binding = 0) uniform Scene {
+    layout(column_major) mat4 view;
+    highp ivec2 size[2][3], offsets[2];
+    Light lights[LIGHT_COUNT];
+} scene;
+
+layout(std430) buffer Particles {
+    uint count;
+    Light particles[];
+};
+
+void main() {}
+";
+
+#[test]
+fn rust_from_glsl() {
+    let declarations = parse_declarations(SHADER).unwrap();
+    let names: Vec<_> = declarations.iter().map(|d| (d.name.as_str(), d.block)).collect();
+    assert_eq!(names,
+               vec![("Light", None), ("Scene", Some(BlockKind::Uniform)), ("Particles", Some(BlockKind::Buffer))]);
+    let size = &declarations[1].members[1];
+    assert_eq!((size.name.as_str(), size.dimensions.clone()), ("size", vec![Some(2), Some(3)]));
+
+    let rust = rust_definitions(SHADER).unwrap();
+    assert_eq!(rust,
+               "#[repr(C, packed)]\n#[derive(Debug, Copy, Clone, DynamicLayout)]\n#[allow(non_snake_case)]\n\
+                pub struct Light {\n    pub position: dynamiclayout::Vec3,\n    pub radius: f32,\n    \
+                pub transform: dynamiclayout::Matrix4x3,\n}\n\n\
+                #[repr(C, packed)]\n#[derive(Debug, Copy, Clone, DynamicLayout)]\n#[allow(non_snake_case)]\n\
+                pub struct Scene {\n    pub view: dynamiclayout::Matrix4,\n    \
+                pub size: [[dynamiclayout::IVec2; 3]; 2],\n    pub offsets: [dynamiclayout::IVec2; 2],\n    \
+                pub lights: [Light; 4],\n}\n\n\
+                #[repr(C, packed)]\n#[derive(Debug, Copy, Clone, DynamicLayout)]\n#[allow(non_snake_case)]\n\
+                pub struct Particles {\n    pub count: u32,\n    \
+                pub particles: dynamiclayout::unsized_array::Unsized<Light>,\n}\n");
+
+    // Generated GLSL parses back to the same fields
+    let glsl = block_declaration::<Foo>(BlockKind::Uniform, Packing::Std140, None).unwrap();
+    let declarations = parse_declarations(&glsl).unwrap();
+    let foo = &declarations[1];
+    let fields: Vec<_> = foo.members.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(fields, vec!["three", "one", "four", "two", "compound"]);
+
+    let error = rust_definitions("struct A {\n    float a;\n};\nbuffer B {\n    bool b;\n};").err().unwrap();
+    assert_eq!(error.line, 4);
+    let message = error.to_string();
+    assert_eq!(message, "line 4: B.b: no Rust type for bool");
+    assert!(rust_definitions("buffer B { float a[]; float b; };").is_err());
+    let error = parse_declarations("uniform B {\n float a[N];\n};").err().unwrap();
+    assert_eq!(error.line, 2);
+
+    // Row-major matrices would be read transposed
+    let error = parse_declarations("uniform B {\n    layout(row_major) mat4 m;\n};").err().unwrap();
+    assert_eq!(error.line, 2);
+    assert_eq!(error.message, "row-major matrices are not supported");
+    assert!(parse_declarations("layout(std140, row_major) uniform B { mat4 m; };").is_err());
+    assert!(rust_definitions("layout(row_major) uniform;\nuniform B { mat4 m; };").is_err());
+    let error = parse_declarations("struct S {\n    mat4 m;\n};\nlayout(row_major) uniform B {\n    S s;\n};")
+        .err()
+        .unwrap();
+    assert_eq!(error.line, 5);
+
+    // Qualifiers that leave every matrix column-major are fine
+    assert!(parse_declarations("layout(row_major) uniform;\nuniform B { float f; };").is_ok());
+    assert!(parse_declarations("layout(row_major) buffer;\nuniform B { mat4 m; };").is_ok());
+    assert!(parse_declarations("layout(row_major) uniform;\nlayout(column_major) uniform;\nuniform B { mat4 m; };")
+        .is_ok());
+    assert!(parse_declarations("layout(std140, row_major) uniform B { vec4 v; layout(column_major) mat4 m; };")
+        .is_ok());
+    assert!(parse_declarations("struct S { layout(column_major) mat4 m; };\nlayout(row_major) uniform B { S s; };")
+        .is_ok());
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Pair {
+    pub x: f32,
+    pub y: Vec2,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Registers {
+    pub a: Vec3,
+    pub b: f32,
+    pub c: Vec2,
+    pub d: Vec2,
+    pub e: f32,
+    pub f: Vec3,
+    pub g: [Vec2; 2],
+    pub h: f32,
+    pub m: Matrix4x3,
+    pub i: f32,
+    pub s: Pair,
+    pub j: f32,
+    pub pairs: [Pair; 2],
+    pub k: f32,
+}
+
+#[test]
+fn hlsl_cbuffer_layout() {
+    let computed = compute_layout::<Registers, _>(&HlslCbuffer).unwrap();
+    let error = compute_layout::<Vec4, _>(&HlslCbuffer).err().unwrap();
+    assert_eq!(error.kind, LayoutErrorKind::UnsupportedType);
+    let layout = Registers::load_layout(&computed).unwrap();
+    let offsets: Vec<_> = <Registers as LayoutDynamicField>::get_named_field_spans(&layout, "")
+        .map(|named| (named.path, named.span.offset))
+        .collect();
+    let expected = vec![("a", 0),
+                        ("b", 12),
+                        ("c", 16),
+                        ("d", 24),
+                        ("e", 32),
+                        // Fits in the rest of the register
+                        ("f", 36),
+                        ("g[0]", 48),
+                        ("g[1]", 64),
+                        // Packed into the unpadded last element
+                        ("h", 72),
+                        ("m[0]", 80),
+                        ("m[1]", 96),
+                        ("m[2]", 112),
+                        ("m[3]", 128),
+                        ("i", 140),
+                        ("s.x", 144),
+                        ("s.y", 148),
+                        ("j", 156),
+                        ("pairs[0].x", 160),
+                        ("pairs[0].y", 164),
+                        ("pairs[1].x", 176),
+                        ("pairs[1].y", 180),
+                        ("k", 188)];
+    let expected: Vec<_> = expected.into_iter().map(|(path, offset)| (path.to_string(), offset)).collect();
+    assert_eq!(offsets, expected);
+    assert!(validate::<Registers>(&layout).is_valid());
+
+    // The same calculator works for other rules
+    let computed = compute_layout::<Bar, _>(&Std140).unwrap();
+    let layout = Bar::load_layout(&computed).unwrap();
+    let spans: Vec<_> = <Bar as LayoutDynamicField>::get_field_spans(&layout).map(|s| s.offset).collect();
+    assert_eq!(spans, vec![0, 16, 32, 48, 64, 80]);
+}
+
+fn span_offsets<T: LayoutDynamicField>(layout: &T::Layout) -> Vec<(String, u32)> {
+    T::get_named_field_spans(layout, "").map(|named| (named.path, named.span.offset)).collect()
+}
+
+fn expected_offsets(expected: &[(&str, u32)]) -> Vec<(String, u32)> {
+    expected.iter().map(|&(path, offset)| (path.to_string(), offset)).collect()
+}
+
+const WGSL: &'static str = "
+struct Bar {
+    one: f32,
+    four: vec4<f32>,
+    matrix: mat4x4f,
+}
+
+/* Explicit attributes /* nested comment */ */
+struct Foo {
+    three: vec3<f32>,
+    one: f32,
+    @size(32) four: vec4f,
+    @align(16) two: vec2<f32>,
+    compound: Bar,
+};
+
+struct Particles {
+    count: atomic<u32>,
+    particles: array<Qux>,
+}
+
+struct Qux {
+    one: f32,
+    four: vec4<f32>,
+}
+
+struct MatrixArray {
+    array: array<mat2x3<f32>, 2>,
+}
+
+@group(0) @binding(0) var<uniform> foo: Foo;
+";
+
+#[test]
+fn wgsl_layouts() {
+    // Rules for derived structs
+    let layout = Foo::load_layout(&compute_layout::<Foo, _>(&Wgsl).unwrap()).unwrap();
+    assert_eq!(span_offsets::<Foo>(&layout),
+               expected_offsets(&[("three", 0),
+                                  ("one", 12),
+                                  ("four", 16),
+                                  ("two", 32),
+                                  ("compound.one", 48),
+                                  ("compound.four", 64),
+                                  ("compound.matrix[0]", 80),
+                                  ("compound.matrix[1]", 96),
+                                  ("compound.matrix[2]", 112),
+                                  ("compound.matrix[3]", 128)]));
+    let layout = Matrix2Array::load_layout(&compute_layout::<Matrix2Array, _>(&Wgsl).unwrap()).unwrap();
+    assert_eq!(span_offsets::<Matrix2Array>(&layout),
+               expected_offsets(&[("array[0][0]", 0), ("array[0][1]", 8), ("array[1][0]", 16), ("array[1][1]", 24)]));
+
+    // Parsed structs with attributes
+    let structs = parse_structs(WGSL).unwrap();
+    let names: Vec<_> = structs.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["Bar", "Foo", "Particles", "Qux", "MatrixArray"]);
+    assert_eq!(structs[1].members[2].size, Some(32));
+    let particles = structs[2].members[1].ty.clone();
+    assert_eq!(particles, WgslType::Array(Box::new(WgslType::Struct("Qux".to_string())), None));
+
+    let layout = Foo::load_layout(&struct_layout(&structs, "Foo").unwrap()).unwrap();
+    assert_eq!(span_offsets::<Foo>(&layout),
+               expected_offsets(&[("three", 0),
+                                  ("one", 12),
+                                  ("four", 16),
+                                  ("two", 48),
+                                  ("compound.one", 64),
+                                  ("compound.four", 80),
+                                  ("compound.matrix[0]", 96),
+                                  ("compound.matrix[1]", 112),
+                                  ("compound.matrix[2]", 128),
+                                  ("compound.matrix[3]", 144)]));
+
+    let layout = Particles::load_layout(&struct_layout(&structs, "Particles").unwrap()).unwrap();
+    let mut bytes = vec![0u8; 16 + 32 * 2];
+    let acc = layout.accessor(&mut bytes);
+    assert_eq!(acc.particles.len(), 2);
+
+    let layout = MatrixArray::load_layout(&struct_layout(&structs, "MatrixArray").unwrap()).unwrap();
+    assert_eq!(span_offsets::<MatrixArray>(&layout),
+               expected_offsets(&[("array[0][0]", 0), ("array[0][1]", 16), ("array[1][0]", 32), ("array[1][1]", 48)]));
+
+    let error = struct_layout(&structs, "Missing").err().unwrap();
+    assert_eq!(error.kind, LayoutErrorKind::MissingBlock("Missing".to_string()));
+    let structs = parse_structs("struct A { @size(8) v: vec3f }").unwrap();
+    let error = struct_layout(&structs, "A").err().unwrap();
+    assert_eq!(error.path, "v");
+    assert!(parse_structs("struct A {\n    b: bool,\n}").is_err());
+
+    let invalid = |source: &str, name: &str| {
+        let structs = parse_structs(source).unwrap();
+        let error = struct_layout(&structs, name).err().unwrap();
+        match error.kind {
+            LayoutErrorKind::InvalidMember(reason) => (error.path, reason),
+            other => panic!("unexpected error {:?}", other),
+        }
+    };
+    assert_eq!(invalid("struct A { @align(12) v: vec3f }", "A"),
+               ("v".to_string(), "@align is not a power of two".to_string()));
+    assert_eq!(invalid("struct A { @align(8) v: vec4f }", "A"),
+               ("v".to_string(), "@align is smaller than the alignment of the type".to_string()));
+
+    // Structs containing themselves have no layout
+    assert_eq!(invalid("struct Node { value: f32, next: Node }", "Node"),
+               ("next".to_string(), "struct Node contains itself".to_string()));
+    assert_eq!(invalid("struct A { b: B }\nstruct B { a: array<A, 2> }", "A"),
+               ("b.a".to_string(), "struct A contains itself".to_string()));
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct Matrix2Array {
+    array: [Matrix2; 2],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+pub struct PackedMembers {
+    pub a: f32,
+    pub b: PackedVec3,
+    pub c: f32,
+    pub d: Vec2,
+    pub e: Vec3,
+}
+
+#[test]
+fn msl_layouts() {
+    let rules = Msl::default();
+    let layout = Padded::load_layout(&compute_layout::<Padded, _>(&rules).unwrap()).unwrap();
+    assert_eq!(span_offsets::<Padded>(&layout),
+               expected_offsets(&[("a", 0), ("b", 16), ("c", 32), ("d", 40)]));
+    assert_eq!(rules.size(&<Padded as DescribeType>::TYPE_INFO), 48);
+
+    // Only the packed_float3 member is packed
+    let layout = PackedMembers::load_layout(&compute_layout::<PackedMembers, _>(&rules).unwrap()).unwrap();
+    assert_eq!(span_offsets::<PackedMembers>(&layout),
+               expected_offsets(&[("a", 0), ("b", 4), ("c", 16), ("d", 24), ("e", 32)]));
+    assert_eq!(rules.size(&<PackedMembers as DescribeType>::TYPE_INFO), 48);
+
+    // Other rules treat it like a vec3
+    let layout = PackedMembers::load_layout(&compute_layout::<PackedMembers, _>(&Std140).unwrap()).unwrap();
+    assert_eq!(span_offsets::<PackedMembers>(&layout),
+               expected_offsets(&[("a", 0), ("b", 16), ("c", 28), ("d", 32), ("e", 48)]));
+    let glsl = block_declaration::<PackedMembers>(BlockKind::Uniform, Packing::Std140, None).unwrap();
+    assert!(glsl.contains("    vec3 b;\n"));
+
+    // float2x3 has float3 columns
+    let layout = MatrixArray::load_layout(&compute_layout::<MatrixArray, _>(&rules).unwrap()).unwrap();
+    assert_eq!(span_offsets::<MatrixArray>(&layout),
+               expected_offsets(&[("array[0][0]", 0), ("array[0][1]", 16), ("array[1][0]", 32), ("array[1][1]", 48)]));
+
+    let layout = Foo::load_layout(&compute_layout::<Foo, _>(&rules).unwrap()).unwrap();
+    assert_eq!(span_offsets::<Foo>(&layout),
+               expected_offsets(&[("three", 0),
+                                  ("one", 16),
+                                  ("four", 32),
+                                  ("two", 48),
+                                  ("compound.one", 64),
+                                  ("compound.four", 80),
+                                  ("compound.matrix[0]", 96),
+                                  ("compound.matrix[1]", 112),
+                                  ("compound.matrix[2]", 128),
+                                  ("compound.matrix[3]", 144)]));
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+#[dynamiclayout(std140)]
+pub struct StaticLight {
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+#[dynamiclayout(std140)]
+pub struct StaticBlock {
+    pub scale: f32,
+    pub position: Vec3,
+    pub transform: Matrix4,
+    pub weights: [f32; 3],
+    pub lights: [StaticLight; 2],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+#[dynamiclayout(std430)]
+pub struct StorageBlock {
+    pub scale: f32,
+    pub offset: Vec2,
+    pub rotation: Matrix2,
+    pub weights: [f32; 3],
+    pub lights: [StaticLight; 2],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, DynamicLayout)]
+#[dynamiclayout(std430)]
+pub struct StaticParticles {
+    pub count: u32,
+    pub positions: Unsized<Vec3>,
+}
+
+#[test]
+fn static_layouts() {
+    const BLOCK_SIZE: usize = StaticBlock::SIZE;
+    assert_eq!(BLOCK_SIZE, 176);
+    assert_eq!(StaticBlock::OFFSETS, [0, 16, 32, 96, 144]);
+    assert_eq!(StaticLight::OFFSETS, [0, 12]);
+    assert_eq!(StaticLight::SIZE, 16);
+
+    let mut static_bytes = [0u8; BLOCK_SIZE];
+    {
+        let acc = StaticBlock::static_accessor(&mut static_bytes);
+        *acc.scale = 2.0;
+        *acc.position = Vec3::new(1.0, 2.0, 3.0);
+        *acc.transform[3] = [4.0, 5.0, 6.0, 1.0];
+        *acc.weights[2] = 0.5;
+        *acc.lights[1].intensity = 8.0;
+    }
+
+    // The dynamic path with the same rules agrees
+    let layout = StaticBlock::load_layout(&compute_layout::<StaticBlock, _>(&Std140).unwrap()).unwrap();
+    let mut dynamic_bytes = [0u8; BLOCK_SIZE];
+    {
+        let mut acc = layout.accessor(&mut dynamic_bytes);
+        *acc.scale = 2.0;
+        *acc.position = Vec3::new(1.0, 2.0, 3.0);
+        *acc.transform[3] = [4.0, 5.0, 6.0, 1.0];
+        acc.weights[2] = 0.5;
+        *acc.lights.index(1).intensity = 8.0;
+    }
+    assert_eq!(&static_bytes[..], &dynamic_bytes[..]);
+
+    // std430 packs arrays and two-row matrix columns tighter
+    assert_eq!(StorageBlock::RULES, ConstRules::Std430);
+    assert_eq!(StorageBlock::OFFSETS, [0, 8, 16, 32, 48]);
+    assert_eq!(StorageBlock::SIZE, Std430.size(&<StorageBlock as DescribeType>::TYPE_INFO));
+    let mut static_bytes = [0u8; StorageBlock::SIZE];
+    {
+        let acc = StorageBlock::static_accessor(&mut static_bytes);
+        *acc.offset = Vec2::new(1.0, 2.0);
+        *acc.rotation[1] = [3.0, 4.0];
+        *acc.weights[2] = 0.5;
+        *acc.lights[1].intensity = 8.0;
+    }
+    let layout = StorageBlock::load_layout(&compute_layout::<StorageBlock, _>(&Std430).unwrap()).unwrap();
+    let mut dynamic_bytes = [0u8; StorageBlock::SIZE];
+    {
+        let mut acc = layout.accessor(&mut dynamic_bytes);
+        *acc.offset = Vec2::new(1.0, 2.0);
+        *acc.rotation[1] = [3.0, 4.0];
+        acc.weights[2] = 0.5;
+        *acc.lights.index(1).intensity = 8.0;
+    }
+    assert_eq!(&static_bytes[..], &dynamic_bytes[..]);
+    assert_eq!(&static_bytes[28..32], &4.0f32.to_ne_bytes());
+
+    // A runtime-sized array holds the elements that fit, the last one without its padding
+    assert_eq!(StaticParticles::OFFSETS, [0, 16]);
+    assert_eq!(StaticParticles::SIZE, 16);
+    let mut bytes = [0u8; 44];
+    {
+        let mut acc = StaticParticles::static_accessor(&mut bytes);
+        *acc.count = 2;
+        assert_eq!(acc.positions.len(), 2);
+        assert!(acc.positions.get(2).is_none());
+        *acc.positions.index(1) = Vec3::new(1.0, 2.0, 3.0);
+    }
+    assert_eq!(&bytes[40..44], &3.0f32.to_ne_bytes());
+    assert_eq!(visited_paths(&mut StaticParticles::static_accessor(&mut bytes)),
+               vec!["count".to_string(), "positions[0]".to_string(), "positions[1]".to_string()]);
+}
+
+// Paths of the fields an accessor visits, without knowing its struct
+fn visited_paths<A: VisitFields>(accessor: &mut A) -> Vec<String> {
+    let mut paths = Vec::new();
+    accessor.visit_fields("", &mut |path: &str, _: FieldValue| paths.push(path.to_string()));
+    paths
+}
+
+#[test]
+fn reflection() {
+    let fields: Vec<_> = StaticBlock::FIELDS.iter().map(|f| (f.name, f.type_name, f.kind)).collect();
+    assert_eq!(fields,
+               vec![("scale", "f32", FieldKind::Primitive(ScalarType::Float)),
+                    ("position", "Vec3", FieldKind::Vector(ScalarType::Float, 3)),
+                    ("transform", "Matrix4", FieldKind::Matrix { columns: 4, rows: 4 }),
+                    ("weights", "[f32; 3]", FieldKind::Array(Some(3))),
+                    ("lights", "[StaticLight; 2]", FieldKind::Array(Some(2)))]);
+    assert_eq!(StaticLight::FIELDS[0].kind, FieldKind::Vector(ScalarType::Float, 3));
+    assert_eq!(Particles::FIELDS[1].kind, FieldKind::Array(None));
+
+    let expected: Vec<String> = ["scale", "position", "transform[0]", "transform[1]", "transform[2]",
+                                 "transform[3]", "weights[0]", "weights[1]", "weights[2]",
+                                 "lights[0].color", "lights[0].intensity", "lights[1].color",
+                                 "lights[1].intensity"]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+    let layout = StaticBlock::load_layout(&compute_layout::<StaticBlock, _>(&Std140).unwrap()).unwrap();
+    let mut bytes = [0u8; StaticBlock::SIZE];
+    assert_eq!(visited_paths(&mut layout.accessor(&mut bytes)), expected.clone());
+    assert_eq!(visited_paths(&mut StaticBlock::static_accessor(&mut bytes)), expected);
+
+    // Typed values can be changed through the visitor
+    layout.accessor(&mut bytes).visit_fields("", &mut |path: &str, value: FieldValue| {
+        match value {
+            FieldValue::Float(x) => *x = path.len() as f32,
+            FieldValue::Vec3(v) => *v = Vec3::new(1.0, 2.0, 3.0),
+            FieldValue::Column4(column) => column[0] = 7.0,
+            _ => panic!("unexpected value at {}", path),
+        }
+    });
+    let acc = StaticBlock::static_accessor(&mut bytes);
+    assert_eq!(*acc.scale, 5.0);
+    assert_eq!({ acc.position.z }, 3.0);
+    assert_eq!(acc.transform[3][0], 7.0);
+    assert_eq!(*acc.weights[1], 10.0);
+    assert_eq!(*acc.lights[1].intensity, 19.0);
+}
+
+// Neither struct is Copy, whole values are still copied field by field
+#[repr(C, packed)]
+#[derive(DynamicLayout)]
+pub struct OwnedLight {
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+#[repr(C, packed)]
+#[derive(DynamicLayout)]
+pub struct OwnedLights {
+    pub count: u32,
+    pub main: OwnedLight,
+    pub extra: [OwnedLight; 2],
+}
+
+#[test]
+fn non_copy_structs() {
+    let layout = compute_layout::<OwnedLights, _>(&Std140).unwrap();
+    let mut buffer = DynamicBuffer::<OwnedLights>::new(OwnedLights::load_layout(&layout).unwrap());
+    let light = |intensity| OwnedLight { color: Vec3::new(1.0, 0.5, 0.0), intensity: intensity };
+    let lights = OwnedLights { count: 3, main: light(1.0), extra: [light(2.0), light(3.0)] };
+    buffer.write(&lights);
+    assert_eq!(*buffer.accessor().extra.index(1).intensity, 3.0);
+    let read = buffer.read();
+    assert_eq!({ read.count }, 3);
+    assert_eq!({ read.main.intensity }, 1.0);
+    assert_eq!({ read.extra[1].intensity }, 3.0);
 }
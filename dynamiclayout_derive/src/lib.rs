@@ -8,9 +8,9 @@ extern crate quote;
 
 use proc_macro::TokenStream;
 use quote::{Ident, Tokens};
-use syn::{DeriveInput, Body, VariantData, Field, Ty};
+use syn::{DeriveInput, Body, VariantData, Field, Ty, Attribute, MetaItem, NestedMetaItem};
 
-#[proc_macro_derive(DynamicLayout)]
+#[proc_macro_derive(DynamicLayout, attributes(dynamiclayout))]
 pub fn derive_dynamiclayout(input: TokenStream) -> TokenStream {
     let input_string = input.to_string();
     let ast = syn::parse_macro_input(&input_string).unwrap();
@@ -24,16 +24,26 @@ fn make_types(ast: &DeriveInput) -> Tokens {
         let layout_name = Ident::new(original_name.to_string() + "Layout");
         let layout_fields = layout_fields(fields);
         let layout_field_spans = layout_field_spans(fields);
+        let layout_named_field_spans = layout_named_field_spans(fields);
         let layout_init = layout_init(fields);
         let accessor_name = Ident::new(original_name.to_string() + "Accessor");
         let accessor_fields = accessor_fields(fields);
         let accessor_init = accessor_init(fields);
+        let field_infos = field_infos(fields);
+        let write_fields = write_fields(fields);
+        let read_fields = read_fields(fields);
+        let reflected_fields = reflected_fields(fields);
+        let visit_fields = visit_fields(fields);
+        let static_layout = match const_rules(&ast.attrs) {
+            Some(rules) => static_layout(original_name, fields, rules),
+            None => quote! {},
+        };
         quote! {
 
             impl dynamiclayout::DynamicLayout for #original_name {
                 #[allow(dead_code)]
-                fn load_layout(layout: &dynamiclayout::LoadStructLayout) -> Result<#layout_name, ()> {
-                    <Self as dynamiclayout::LayoutDynamicField>::make_layout(dynamiclayout::LayoutInfo::StructField(layout))
+                fn load_layout_with_options(layout: &dynamiclayout::LoadStructLayout, options: &dynamiclayout::LoadOptions) -> Result<#layout_name, dynamiclayout::LayoutError> {
+                    dynamiclayout::field_matching::load_struct_layout::<Self>(layout, options)
                 }
             }
 
@@ -45,7 +55,7 @@ fn make_types(ast: &DeriveInput) -> Tokens {
                 #[allow(dead_code)]
                 pub fn accessor<'a>(&'a self, bytes: &'a mut[u8]) -> #accessor_name<'a> {
                     unsafe {
-                        <#original_name as dynamiclayout::AccessDynamicField>::accessor_from_layout(self, bytes.as_mut_ptr())
+                        <#original_name as dynamiclayout::AccessDynamicField>::accessor_from_layout(self, bytes.as_mut_ptr(), bytes.len())
                     }
                 }
             }
@@ -57,13 +67,12 @@ fn make_types(ast: &DeriveInput) -> Tokens {
             impl dynamiclayout::LayoutDynamicField for #original_name {
                 type Layout = #layout_name;
 
-                fn make_layout(layout: dynamiclayout::LayoutInfo) -> Result<Self::Layout, ()> {
-                    if let dynamiclayout::LayoutInfo::StructField(ref layout) = layout {
-                        Ok(#layout_name {
+                fn make_layout(layout: dynamiclayout::LayoutInfo, options: &dynamiclayout::LoadOptions) -> Result<Self::Layout, dynamiclayout::LayoutError> {
+                    match layout {
+                        dynamiclayout::LayoutInfo::StructField(ref layout) => Ok(#layout_name {
                             #(#layout_init),*
-                        })
-                    } else {
-                        Err(())
+                        }),
+                        other => Err(other.unexpected("struct")),
                     }
                 }
 
@@ -73,99 +82,248 @@ fn make_types(ast: &DeriveInput) -> Tokens {
                         #(#layout_field_spans)*
                     )
                 }
+
+                fn get_named_field_spans(layout: &Self::Layout, path: &str) -> Box<Iterator<Item = dynamiclayout::NamedFieldSpan>> {
+                    Box::new(
+                        ::std::iter::empty()
+                        #(#layout_named_field_spans)*
+                    )
+                }
             }
 
             impl dynamiclayout::LayoutArrayDynamicField for #original_name {
-                type Layout = Vec<#layout_name>;
-
-                fn make_layout(layout: dynamiclayout::LayoutInfo, _: usize) -> Result<Self::Layout, ()> {
-                    if let dynamiclayout::LayoutInfo::StructArrayField(ref layouts) = layout {
-                        let mut output = Vec::with_capacity(layouts.len());
-                        for input in layouts.iter() {
-                            let layout_field = LayoutInfo::StructField(*input);
-                            output.push(try!(<#original_name as LayoutDynamicField>::make_layout(layout_field)));
-                        }
-                        Ok(output)
-                    } else {
-                        Err(())
-                    }
+                type Layout = dynamiclayout::struct_array::StructArrayLayout<#layout_name>;
+
+                fn make_layout(layout: dynamiclayout::LayoutInfo, len: usize, options: &dynamiclayout::LoadOptions) -> Result<Self::Layout, dynamiclayout::LayoutError> {
+                    dynamiclayout::struct_array::StructArrayLayout::make::<#original_name>(layout, len, options)
                 }
 
-                fn get_field_spans(layout: &Self::Layout, _: usize) -> Box<Iterator<Item = dynamiclayout::FieldSpan>> {
-                    let spans: Vec<_> = layout.iter().flat_map(|l| <#original_name as LayoutDynamicField>::get_field_spans(l)).collect();
-                    Box::new(spans.into_iter())
+                fn len(layout: &Self::Layout) -> usize {
+                    layout.len()
+                }
+
+                fn get_field_spans(layout: &Self::Layout, len: usize) -> Box<Iterator<Item = dynamiclayout::FieldSpan>> {
+                    layout.field_spans::<#original_name>(len)
+                }
+
+                fn get_named_field_spans(layout: &Self::Layout, path: &str, len: usize) -> Box<Iterator<Item = dynamiclayout::NamedFieldSpan>> {
+                    layout.named_field_spans::<#original_name>(path, len)
                 }
             }
 
             impl<'a> dynamiclayout::AccessDynamicField<'a> for #original_name {
                 type Accessor = #accessor_name<'a>;
 
-                unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8) -> Self::Accessor {
+                unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, size: usize) -> Self::Accessor {
                     #accessor_name {
                         #(#accessor_init),*
                     }
                 }
             }
 
+            unsafe impl dynamiclayout::CopyDynamicField for #original_name {
+                unsafe fn write_field(layout: &<Self as dynamiclayout::LayoutDynamicField>::Layout, bytes: *mut u8, value: &Self) {
+                    #(#write_fields)*
+                }
+
+                unsafe fn read_field(layout: &<Self as dynamiclayout::LayoutDynamicField>::Layout, bytes: *const u8, out: &mut Self) {
+                    #(#read_fields)*
+                }
+            }
+
+            unsafe impl dynamiclayout::CopyArrayDynamicField for #original_name {
+                unsafe fn write_array(layout: &<Self as dynamiclayout::LayoutArrayDynamicField>::Layout, bytes: *mut u8, values: &[Self]) {
+                    layout.write_elements::<#original_name>(bytes, values)
+                }
+
+                unsafe fn read_array(layout: &<Self as dynamiclayout::LayoutArrayDynamicField>::Layout, bytes: *const u8, out: &mut [Self]) {
+                    layout.read_elements::<#original_name>(bytes, out)
+                }
+            }
+
+            impl dynamiclayout::type_info::DescribeType for #original_name {
+                const TYPE_INFO: dynamiclayout::type_info::TypeInfo = dynamiclayout::type_info::TypeInfo::Struct(&dynamiclayout::type_info::StructInfo {
+                    name: stringify!(#original_name),
+                    fields: &[
+                        #(#field_infos),*
+                    ],
+                });
+            }
+
             impl<'a> dynamiclayout::AccessArrayDynamicField<'a> for #original_name {
-                type Accessor = Vec<#accessor_name<'a>>;
+                type Accessor = dynamiclayout::struct_array::StructArrayAccessor<'a, #original_name>;
 
-                unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, _: usize) -> Self::Accessor {
-                    layout.iter().map(|l| <#original_name as dynamiclayout::AccessDynamicField>::accessor_from_layout(l, bytes)).collect()
+                unsafe fn accessor_from_layout(layout: &'a Self::Layout, bytes: *mut u8, size: usize, len: usize) -> Self::Accessor {
+                    dynamiclayout::struct_array::StructArrayAccessor::new(layout, bytes, size, len)
                 }
             }
+
+            impl dynamiclayout::reflection::Reflect for #original_name {
+                const FIELDS: &'static [dynamiclayout::reflection::ReflectedField] = &[
+                    #(#reflected_fields),*
+                ];
+            }
+
+            impl<'a> dynamiclayout::reflection::VisitFields for #accessor_name<'a> {
+                fn visit_fields(&mut self, path: &str, visitor: &mut dynamiclayout::reflection::FieldVisitor) {
+                    #(#visit_fields)*
+                }
+            }
+
+            #static_layout
         }
     } else {
         panic!("Only structs with named fields are supported")
     }
 }
 
-fn layout_fields(fields: &Vec<Field>) -> Vec<Tokens> {
+// Rules given with `#[dynamiclayout(std140)]` or `#[dynamiclayout(std430)]` to compute the
+// layout at compile time
+fn const_rules(attrs: &[Attribute]) -> Option<Tokens> {
+    let mut rules = None;
+    for attr in attrs {
+        if let MetaItem::List(ref ident, ref items) = attr.value {
+            if ident == "dynamiclayout" {
+                for item in items {
+                    rules = match *item {
+                        NestedMetaItem::MetaItem(MetaItem::Word(ref word)) if word == "std140" => {
+                            Some(quote! { dynamiclayout::layout_rules::ConstRules::Std140 })
+                        }
+                        NestedMetaItem::MetaItem(MetaItem::Word(ref word)) if word == "std430" => {
+                            Some(quote! { dynamiclayout::layout_rules::ConstRules::Std430 })
+                        }
+                        _ => panic!("Only #[dynamiclayout(std140)] and #[dynamiclayout(std430)] are supported"),
+                    };
+                }
+            }
+        }
+    }
+    rules
+}
+
+fn static_layout(original_name: &syn::Ident, fields: &[Field], rules: Tokens) -> Tokens {
+    let accessor_name = Ident::new(original_name.to_string() + "StaticAccessor");
+    let field_count = fields.len();
+    let accessor_fields: Vec<Tokens> = fields.iter().map(|field| {
+        let name = field.ident.clone().unwrap();
+        let ty = &field.ty;
+        quote! { #name : <#ty as dynamiclayout::static_layout::AccessStaticField<'a>>::Accessor }
+    }).collect();
+    let accessor_init: Vec<Tokens> = fields.iter().enumerate().map(|(index, field)| {
+        let name = field.ident.clone().unwrap();
+        let ty = &field.ty;
+        quote! { #name : <#ty as dynamiclayout::static_layout::AccessStaticField<'a>>::static_accessor(bytes.add(offsets[#index]), size.saturating_sub(offsets[#index]), rules) }
+    }).collect();
+    let visit_fields = visit_fields(fields);
+    quote! {
+        impl #original_name {
+            pub const RULES: dynamiclayout::layout_rules::ConstRules = #rules;
+            pub const OFFSETS: [usize; #field_count] = Self::RULES.offsets::<#field_count>(&<#original_name as dynamiclayout::type_info::DescribeType>::TYPE_INFO);
+            pub const SIZE: usize = Self::RULES.size(&<#original_name as dynamiclayout::type_info::DescribeType>::TYPE_INFO);
+
+            #[allow(dead_code)]
+            pub fn static_accessor<'a>(bytes: &'a mut [u8]) -> #accessor_name<'a> {
+                if bytes.len() < Self::SIZE {
+                    panic!("{} needs {} bytes but the buffer has {}", stringify!(#original_name), Self::SIZE, bytes.len());
+                }
+                unsafe {
+                    <#original_name as dynamiclayout::static_layout::AccessStaticField<'a>>::static_accessor(bytes.as_mut_ptr(), bytes.len(), Self::RULES)
+                }
+            }
+        }
+
+        pub struct #accessor_name<'a> {
+            #(#accessor_fields),*
+        }
+
+        impl<'a> dynamiclayout::reflection::VisitFields for #accessor_name<'a> {
+            fn visit_fields(&mut self, path: &str, visitor: &mut dynamiclayout::reflection::FieldVisitor) {
+                #(#visit_fields)*
+            }
+        }
+
+        impl<'a> dynamiclayout::static_layout::AccessStaticField<'a> for #original_name {
+            type Accessor = #accessor_name<'a>;
+
+            unsafe fn static_accessor(bytes: *mut u8, size: usize, rules: dynamiclayout::layout_rules::ConstRules) -> Self::Accessor {
+                // The offsets under both rules are computed at compile time
+                let offsets = match rules {
+                    dynamiclayout::layout_rules::ConstRules::Std140 => const { dynamiclayout::layout_rules::ConstRules::Std140.offsets::<#field_count>(&<#original_name as dynamiclayout::type_info::DescribeType>::TYPE_INFO) },
+                    dynamiclayout::layout_rules::ConstRules::Std430 => const { dynamiclayout::layout_rules::ConstRules::Std430.offsets::<#field_count>(&<#original_name as dynamiclayout::type_info::DescribeType>::TYPE_INFO) },
+                };
+                #accessor_name {
+                    #(#accessor_init),*
+                }
+            }
+        }
+    }
+}
+
+// A field is `None` if a lenient load found no layout for it
+fn layout_fields(fields: &[Field]) -> Vec<Tokens> {
     fields.iter().map(|field| {
         let name = field.ident.clone().unwrap();
         let ty = &field.ty;
         match *ty {
-            Ty::Array(ref inner_ty, _) => quote! { #name: <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::Layout },
-            _ => quote! { #name: <#ty as dynamiclayout::LayoutDynamicField>::Layout }
+            Ty::Array(ref inner_ty, _) => quote! { #name: Option<<#inner_ty as dynamiclayout::LayoutArrayDynamicField>::Layout> },
+            _ => quote! { #name: Option<<#ty as dynamiclayout::LayoutDynamicField>::Layout> }
         }
     }).collect()
 }
 
-fn layout_init(fields: &Vec<Field>) -> Vec<Tokens> {
+fn layout_init(fields: &[Field]) -> Vec<Tokens> {
     fields.iter().map(|field| {
         let name = field.ident.clone().unwrap();
         let ty = &field.ty;
-        match *ty {
-            Ty::Array(ref inner_ty, ref size) => {
-                quote! {
-                    #name: layout
-                        .get_field_layout(stringify!(#name))
-                        .ok_or(())
-                        .and_then(|l| <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::make_layout(l, #size))?
-                }},
-            _ =>
-                quote! {
-                    #name: layout
-                        .get_field_layout(stringify!(#name))
-                        .ok_or(())
-                        .and_then(<#ty as dynamiclayout::LayoutDynamicField>::make_layout)?
-                }
+        let make_layout = match *ty {
+            Ty::Array(ref inner_ty, ref size) => quote! { <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::make_layout(l, #size, options) },
+            _ => quote! { <#ty as dynamiclayout::LayoutDynamicField>::make_layout(l, options) }
+        };
+        quote! {
+            #name: match layout.get_field_layout(stringify!(#name)) {
+                Some(l) => Some(#make_layout.map_err(|e| e.in_field(stringify!(#name)))?),
+                None if options.fields == dynamiclayout::FieldMatching::Lenient => None,
+                None => return Err(dynamiclayout::LayoutError::new(dynamiclayout::LayoutErrorKind::MissingField).in_field(stringify!(#name))),
+            }
         }
     }).collect()
 }
 
-fn layout_field_spans(fields: &Vec<Field>) -> Vec<Tokens> {
+fn layout_field_spans(fields: &[Field]) -> Vec<Tokens> {
     fields.iter().map(|field| {
         let name = field.ident.clone().unwrap();
         let ty = &field.ty;
-        match *ty {
-            Ty::Array(ref inner_ty, ref size) => quote! { .chain(<#inner_ty as dynamiclayout::LayoutArrayDynamicField>::get_field_spans(&layout.#name, #size)) },
-            _ => quote! { .chain(<#ty as dynamiclayout::LayoutDynamicField>::get_field_spans(&layout.#name)) }
+        let spans = match *ty {
+            Ty::Array(ref inner_ty, _) => quote! { <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::get_field_spans(l, <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::len(l)) },
+            _ => quote! { <#ty as dynamiclayout::LayoutDynamicField>::get_field_spans(l) }
+        };
+        quote! {
+            .chain(match layout.#name {
+                Some(ref l) => #spans,
+                None => Box::new(::std::iter::empty()),
+            })
         }
     }).collect()
 }
 
-fn accessor_fields(fields: &Vec<Field>) -> Vec<Tokens> {
+fn layout_named_field_spans(fields: &[Field]) -> Vec<Tokens> {
+    fields.iter().map(|field| {
+        let name = field.ident.clone().unwrap();
+        let ty = &field.ty;
+        let spans = match *ty {
+            Ty::Array(ref inner_ty, _) => quote! { <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::get_named_field_spans(l, &dynamiclayout::join_field_path(path, stringify!(#name)), <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::len(l)) },
+            _ => quote! { <#ty as dynamiclayout::LayoutDynamicField>::get_named_field_spans(l, &dynamiclayout::join_field_path(path, stringify!(#name))) }
+        };
+        quote! {
+            .chain(match layout.#name {
+                Some(ref l) => #spans,
+                None => Box::new(::std::iter::empty()),
+            })
+        }
+    }).collect()
+}
+
+fn accessor_fields(fields: &[Field]) -> Vec<Tokens> {
     fields.iter().map(|field| {
         let name = field.ident.clone().unwrap();
         let ty = &field.ty;
@@ -176,13 +334,100 @@ fn accessor_fields(fields: &Vec<Field>) -> Vec<Tokens> {
     }).collect()
 }
 
-fn accessor_init(fields: &Vec<Field>) -> Vec<Tokens> {
+fn accessor_init(fields: &[Field]) -> Vec<Tokens> {
+    fields.iter().map(|field| {
+        let name = field.ident.clone().unwrap();
+        let ty = &field.ty;
+        let field_layout = quote! { dynamiclayout::field_matching::present_layout(&layout.#name, stringify!(#name)) };
+        match *ty {
+            Ty::Array(ref inner_ty, _) => quote! { #name : <#inner_ty as dynamiclayout::AccessArrayDynamicField<'a>>::accessor_from_layout(#field_layout, bytes, size, <#inner_ty as dynamiclayout::LayoutArrayDynamicField>::len(#field_layout)) },
+            _ => quote! { #name : <#ty as dynamiclayout::AccessDynamicField<'a>>::accessor_from_layout(#field_layout, bytes, size) }
+        }
+    }).collect()
+}
+
+fn field_infos(fields: &[Field]) -> Vec<Tokens> {
+    fields.iter().map(|field| {
+        let name = field.ident.clone().unwrap();
+        let ty = &field.ty;
+        quote! {
+            dynamiclayout::type_info::FieldInfo {
+                name: stringify!(#name),
+                ty: &<#ty as dynamiclayout::type_info::DescribeType>::TYPE_INFO,
+            }
+        }
+    }).collect()
+}
+
+fn reflected_fields(fields: &[Field]) -> Vec<Tokens> {
+    fields.iter().map(|field| {
+        let name = field.ident.clone().unwrap();
+        let ty = &field.ty;
+        // quote puts spaces between all tokens, e.g. `[ Light ; 4 ]`
+        let type_name = quote! { #ty }.to_string().replace(' ', "").replace(';', "; ");
+        quote! {
+            dynamiclayout::reflection::ReflectedField {
+                name: stringify!(#name),
+                type_name: #type_name,
+                kind: dynamiclayout::reflection::FieldKind::of(&<#ty as dynamiclayout::type_info::DescribeType>::TYPE_INFO),
+                ty: &<#ty as dynamiclayout::type_info::DescribeType>::TYPE_INFO,
+            }
+        }
+    }).collect()
+}
+
+fn visit_fields(fields: &[Field]) -> Vec<Tokens> {
+    fields.iter().map(|field| {
+        let name = field.ident.clone().unwrap();
+        quote! {
+            dynamiclayout::reflection::VisitFields::visit_fields(&mut self.#name, &dynamiclayout::join_field_path(path, stringify!(#name)), visitor);
+        }
+    }).collect()
+}
+
+// Fields are copied out of and back into the value bitwise since the structs are usually packed
+// and their fields need not be Copy. The copies are never dropped, the value keeps ownership.
+// Fields without a layout are left alone.
+fn write_fields(fields: &[Field]) -> Vec<Tokens> {
     fields.iter().map(|field| {
         let name = field.ident.clone().unwrap();
         let ty = &field.ty;
         match *ty {
-            Ty::Array(ref inner_ty, ref size) => quote! { #name : <#inner_ty as dynamiclayout::AccessArrayDynamicField<'a>>::accessor_from_layout(&layout.#name, bytes, #size) },
-            _ => quote! { #name : <#ty as dynamiclayout::AccessDynamicField<'a>>::accessor_from_layout(&layout.#name, bytes) }
+            Ty::Array(ref inner_ty, _) => quote! {
+                if let Some(ref field_layout) = layout.#name {
+                    let field = ::std::mem::ManuallyDrop::new(::std::ptr::read_unaligned(::std::ptr::addr_of!(value.#name)));
+                    <#inner_ty as dynamiclayout::CopyArrayDynamicField>::write_array(field_layout, bytes, &field[..]);
+                }
+            },
+            _ => quote! {
+                if let Some(ref field_layout) = layout.#name {
+                    let field = ::std::mem::ManuallyDrop::new(::std::ptr::read_unaligned(::std::ptr::addr_of!(value.#name)));
+                    <#ty as dynamiclayout::CopyDynamicField>::write_field(field_layout, bytes, &*field);
+                }
+            }
+        }
+    }).collect()
+}
+
+fn read_fields(fields: &[Field]) -> Vec<Tokens> {
+    fields.iter().map(|field| {
+        let name = field.ident.clone().unwrap();
+        let ty = &field.ty;
+        match *ty {
+            Ty::Array(ref inner_ty, _) => quote! {
+                if let Some(ref field_layout) = layout.#name {
+                    let mut field = ::std::mem::ManuallyDrop::new(::std::ptr::read_unaligned(::std::ptr::addr_of!(out.#name)));
+                    <#inner_ty as dynamiclayout::CopyArrayDynamicField>::read_array(field_layout, bytes, &mut field[..]);
+                    ::std::ptr::write_unaligned(::std::ptr::addr_of_mut!(out.#name), ::std::mem::ManuallyDrop::into_inner(field));
+                }
+            },
+            _ => quote! {
+                if let Some(ref field_layout) = layout.#name {
+                    let mut field = ::std::mem::ManuallyDrop::new(::std::ptr::read_unaligned(::std::ptr::addr_of!(out.#name)));
+                    <#ty as dynamiclayout::CopyDynamicField>::read_field(field_layout, bytes, &mut *field);
+                    ::std::ptr::write_unaligned(::std::ptr::addr_of_mut!(out.#name), ::std::mem::ManuallyDrop::into_inner(field));
+                }
+            }
         }
     }).collect()
 }